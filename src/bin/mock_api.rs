@@ -0,0 +1,356 @@
+//! A stand-in for the parts of the Guild Wars 2 API that gw2_tp uses. It
+//! serves fixtures from JSON files, so the planner can run without a network:
+//!
+//! ```text
+//! mock_api --port 8777 --fixtures fixtures
+//! gw2_tp --api http://127.0.0.1:8777
+//! ```
+//!
+//! The account endpoints serve account.json, if there is one, to any request
+//! with an API key. With `--fail <count>`, the first that many requests fail
+//! with the `--fail-status`, to test how the client retries.
+
+#![allow(clippy::needless_return)]
+
+extern crate argparse;
+use argparse::{ArgumentParser, Store};
+
+use gw2_tp::tp_rest::MAX_IDS_PER_REQUEST;
+
+use serde_json::{json, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Each endpoint that serves entries by ID, and the file its fixtures are in.
+const ENDPOINTS: [(&str, &str); 4] = [
+    ("/v2/commerce/listings", "listings.json"),
+    ("/v2/commerce/prices", "prices.json"),
+    ("/v2/items", "items.json"),
+    ("/v2/recipes", "recipes.json")
+];
+
+struct Fixtures {
+    by_id: HashMap<&'static str, BTreeMap<u64, Value>>,
+    // The materials, bank, inventory and characters of the account
+    account: Value
+}
+
+fn load_fixtures(directory: &Path) -> Result<Fixtures, String> {
+    let mut fixtures = Fixtures{by_id: HashMap::new(), account: Value::Null};
+    for (endpoint, file_name) in &ENDPOINTS {
+        let path = directory.join(file_name);
+        let source = std::fs::read_to_string(&path).map_err(
+            |error| format!("{}: {}", path.display(), error)
+        )?;
+
+        let entries: Vec<Value> = serde_json::from_str(&source).map_err(
+            |error| format!("{}: {}", path.display(), error)
+        )?;
+
+        let mut by_id = BTreeMap::<u64, Value>::new();
+        for entry in entries {
+            match entry["id"].as_u64() {
+                Some(id) => by_id.insert(id, entry),
+                None => {
+                    return Err(format!(
+                        "{}: every entry needs a numeric [id]", path.display()
+                    ));
+                }
+            };
+        }
+
+        fixtures.by_id.insert(endpoint, by_id);
+    }
+
+    let path = directory.join("account.json");
+    if path.exists() {
+        let source = std::fs::read_to_string(&path).map_err(
+            |error| format!("{}: {}", path.display(), error)
+        )?;
+
+        fixtures.account = serde_json::from_str(&source).map_err(
+            |error| format!("{}: {}", path.display(), error)
+        )?;
+    }
+
+    return Ok(fixtures);
+}
+
+// Requests that are made to fail before the fixtures are served.
+struct Failures {
+    count: u32,
+    status: u16,
+    made: AtomicU32
+}
+
+// Decode the percent-encoded bytes of a URL, such as the accented letters of
+// a character name. Anything that is not valid UTF-8 afterwards is replaced.
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::<u8>::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = encoded.get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    return String::from_utf8_lossy(&decoded).into_owned();
+}
+
+fn error(status: u16, text: &str) -> (u16, Value) {
+    return (status, json!({"text": text}));
+}
+
+// Answer a request for one of the account endpoints, which need an API key.
+fn respond_account(fixtures: &Fixtures, path: &str) -> (u16, Value) {
+    let account = &fixtures.account;
+    let characters = path.strip_prefix("/v2/characters");
+    let body = match (path, characters) {
+        ("/v2/account/materials", _) => &account["materials"],
+        ("/v2/account/bank", _) => &account["bank"],
+        ("/v2/account/inventory", _) => &account["inventory"],
+        (_, Some("")) => {
+            let names: Vec<&String> = account["characters"].as_object()
+                .map(|characters| characters.keys().collect())
+                .unwrap_or_default();
+            return (200, json!(names));
+        },
+        (_, Some(character)) => {
+            let name = character.strip_prefix('/')
+                .and_then(|c| c.strip_suffix("/inventory"))
+                .map(percent_decode)
+                .unwrap_or_default();
+            &account["characters"][name.as_str()]
+        },
+        _ => &Value::Null
+    };
+
+    if body.is_null() {
+        return error(404, "not found");
+    }
+
+    return (200, body.clone());
+}
+
+// Answer a request the way the real API would, including its limit on IDs
+// per request and its partial responses for unknown IDs.
+fn respond(fixtures: &Fixtures, target: &str, authorized: bool)
+-> (u16, Value) {
+    let (path, query) = match target.find('?') {
+        Some(split) => (&target[..split], &target[split + 1..]),
+        None => (target, "")
+    };
+
+    let query = query.replace("%2C", ",").replace("%2c", ",");
+    let parameters: HashMap<&str, &str> = query.split('&')
+        .filter_map(|parameter| {
+            let split = parameter.find('=')?;
+            Some((&parameter[..split], &parameter[split + 1..]))
+        })
+        .collect();
+
+    if path.starts_with("/v2/account") || path.starts_with("/v2/characters") {
+        if !authorized {
+            return error(401, "Invalid access token");
+        }
+
+        return respond_account(fixtures, path);
+    }
+
+    if path == "/v2/recipes/search" {
+        let output = match parameters.get("output").map(|o| o.parse::<u64>()) {
+            Some(Ok(output)) => output,
+            _ => return error(400, "missing output or input parameter")
+        };
+
+        let recipes: Vec<u64> = fixtures.by_id["/v2/recipes"].iter()
+            .filter(|(_, recipe)| recipe["output_item_id"] == output)
+            .map(|(id, _)| *id)
+            .collect();
+        return (200, json!(recipes));
+    }
+
+    let entries = match fixtures.by_id.get(path) {
+        Some(entries) => entries,
+        None => return error(404, "not found")
+    };
+
+    let ids: Vec<&str> = match parameters.get("ids") {
+        Some(ids) => ids.split(',').filter(|id| !id.is_empty()).collect(),
+        None => return (200, json!(entries.keys().collect::<Vec<&u64>>()))
+    };
+
+    if ids.len() > MAX_IDS_PER_REQUEST {
+        return error(
+            400,
+            &format!(
+                "id list too long; this endpoint is limited to {} ids at once",
+                MAX_IDS_PER_REQUEST
+            )
+        );
+    }
+
+    let found: Vec<Value> = ids.iter()
+        .filter_map(|id| id.parse::<u64>().ok())
+        .filter_map(|id| entries.get(&id).cloned())
+        .collect();
+
+    if found.is_empty() {
+        return error(404, "all ids provided are invalid");
+    }
+
+    if found.len() < ids.len() {
+        return (206, json!(found));
+    }
+
+    return (200, json!(found));
+}
+
+fn handle(fixtures: &Fixtures, failures: &Failures, stream: TcpStream)
+-> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Only the API key in the headers changes the response
+    let mut authorized = false;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if header.to_lowercase().starts_with("authorization: bearer ") {
+            authorized = true;
+        }
+        header.clear();
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let failure = failures.made.fetch_add(1, Ordering::SeqCst) + 1;
+    let (status, body) =
+        if failure <= failures.count {
+            error(
+                failures.status,
+                &format!("failure {} of {}", failure, failures.count)
+            )
+        } else {
+            respond(fixtures, target, authorized)
+        };
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        _ => "Error"
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    )?;
+    return stream.flush();
+}
+
+fn main() {
+
+    let mut port: u16 = 8777;
+
+    let mut fixtures_directory = "fixtures".to_string();
+
+    let mut failures = Failures{count: 0, status: 503, made: AtomicU32::new(0)};
+
+    {
+        let mut parser = ArgumentParser::new();
+        parser.set_description(
+            "Serve Guild Wars 2 API fixtures for testing gw2_tp without a \
+            network."
+        );
+
+        parser
+            .refer(&mut port)
+            .add_option(&["-p", "--port"], Store, "Port to listen on");
+
+        parser
+            .refer(&mut fixtures_directory)
+            .add_option(
+                &["-f", "--fixtures"],
+                Store,
+                "Directory with listings.json, prices.json, items.json, \
+                recipes.json and optionally account.json"
+            );
+
+        parser
+            .refer(&mut failures.count)
+            .add_option(
+                &["--fail"], Store, "How many of the first requests to fail"
+            );
+
+        parser
+            .refer(&mut failures.status)
+            .add_option(
+                &["--fail-status"],
+                Store,
+                "The status that failed requests answer with (default 503)"
+            );
+
+        parser.parse_args_or_exit();
+    }
+
+    let failures = Arc::new(failures);
+
+    let fixtures = match load_fixtures(Path::new(&fixtures_directory)) {
+        Ok(fixtures) => Arc::new(fixtures),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("Failed to listen on port {}: {}", port, error);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Serving {} at http://127.0.0.1:{}", fixtures_directory, port
+    );
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let fixtures = fixtures.clone();
+                let failures = failures.clone();
+                std::thread::spawn(move || {
+                    if let Err(error) = handle(&fixtures, &failures, stream) {
+                        eprintln!("Failed to respond: {}", error);
+                    }
+                });
+            },
+            Err(error) => eprintln!("Failed to accept a connection: {}", error)
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::database::{Database, ItemId};
+use crate::error::{LoadError, Problem};
+use crate::tp_rest::{self, Item, Listings};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where the listings are cached unless another file is given.
+pub const CACHE_FILE: &str = ".listings-cache.json";
+
+/// How long cached listings are used before they are fetched again, unless
+/// another time is given.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    // Seconds since the Unix epoch
+    fetched: u64,
+    // Items that cannot be traded are cached without listings, so they are
+    // not requested again every time.
+    listings: Option<Item>
+}
+
+fn now() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+}
+
+/// Trading Post listings saved on disk between runs, each with the time it was
+/// fetched.
+#[derive(Clone, Debug)]
+pub struct ListingCache {
+    path: PathBuf,
+    entries: HashMap<ItemId, CacheEntry>
+}
+
+impl ListingCache {
+    /// Load the cache saved at `path`. A cache that has not been saved yet is
+    /// empty.
+    pub fn load(path: &Path) -> Result<ListingCache, LoadError> {
+        let mut cache = ListingCache{
+            path: path.to_path_buf(),
+            entries: HashMap::new()
+        };
+
+        if !path.exists() {
+            return Ok(cache);
+        }
+
+        let source = std::fs::read_to_string(path).map_err(
+            |error| LoadError::Io{file: path.to_path_buf(), error}
+        )?;
+
+        cache.entries = serde_json::from_str(&source).map_err(
+            |error| LoadError::Invalid{
+                file: path.to_path_buf(),
+                problems: vec![Problem::new(
+                    String::new(),
+                    Some(error.line()),
+                    Some(error.column()),
+                    error.to_string()
+                )]
+            }
+        )?;
+
+        return Ok(cache);
+    }
+
+    /// Write the cache back to the file it was loaded from.
+    pub fn save(&self) -> std::io::Result<()> {
+        let sorted: BTreeMap<&ItemId, &CacheEntry> =
+            self.entries.iter().collect();
+        return std::fs::write(&self.path, serde_json::to_string(&sorted)?);
+    }
+
+    /// The `items` that are not cached, or were fetched more than `ttl` ago.
+    pub fn stale(&self, items: &HashSet<ItemId>, ttl: Duration)
+    -> HashSet<ItemId> {
+        let oldest_fresh = now().saturating_sub(ttl.as_secs());
+        return items.iter()
+            .filter(|item| match self.entries.get(item) {
+                Some(entry) => entry.fetched < oldest_fresh,
+                None => true
+            })
+            .copied()
+            .collect();
+    }
+
+    /// Store freshly fetched listings. Any of the `fetched` items without
+    /// listings is remembered as having none.
+    pub fn update(
+        &mut self,
+        fetched: &HashSet<ItemId>,
+        mut listings: HashMap<ItemId, Item>
+    ) {
+        let time = now();
+        for item in fetched {
+            self.entries.insert(
+                *item,
+                CacheEntry{fetched: time, listings: listings.remove(item)}
+            );
+        }
+    }
+
+    /// The cached listings of the `items`, however old they are.
+    pub fn listings(&self, items: &HashSet<ItemId>) -> HashMap<ItemId, Item> {
+        return items.iter()
+            .filter_map(|item| {
+                let listings = self.entries.get(item)?.listings.clone()?;
+                Some((*item, listings))
+            })
+            .collect();
+    }
+
+    /// The `items` that have never been fetched.
+    pub fn missing(&self, items: &HashSet<ItemId>) -> HashSet<ItemId> {
+        return items.iter()
+            .filter(|item| !self.entries.contains_key(item))
+            .copied()
+            .collect();
+    }
+
+    /// When the oldest cached entry among the `items` was fetched.
+    pub fn oldest(&self, items: &HashSet<ItemId>) -> Option<SystemTime> {
+        return items.iter()
+            .filter_map(|item| self.entries.get(item))
+            .map(|entry| UNIX_EPOCH + Duration::from_secs(entry.fetched))
+            .min();
+    }
+
+    /// Get the listings of the `items` from the API at `api_url`, except for
+    /// the ones fetched within the last `ttl`, which come from the cache. Items
+    /// that fail to be fetched fall back on their cached listings, however old
+    /// they are. The cache is updated but not saved.
+    pub fn get_listings(
+        &mut self,
+        api_url: &str,
+        database: &Database,
+        items: &HashSet<ItemId>,
+        ttl: Duration
+    ) -> Listings {
+        let stale = self.stale(items, ttl);
+        let mut fetched = tp_rest::get_listings(api_url, database, &stale);
+
+        let succeeded: HashSet<ItemId> =
+            stale.difference(fetched.failed()).copied().collect();
+        self.update(&succeeded, fetched.listings().clone());
+
+        fetched.fill_in(self.listings(items));
+        return fetched;
+    }
+}
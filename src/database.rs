@@ -0,0 +1,214 @@
+use crate::descriptions::{MaterialDescription, Recipe};
+
+use std::collections::HashMap;
+
+/// The ID of an item in the Guild Wars 2 API. Tradable items use the same ID
+/// on the Trading Post.
+pub type ItemId = u32;
+
+/// Get the item ID out of an item chat link, such as `&AgGqtgAA`.
+pub fn parse_chat_link(link: &str) -> Option<ItemId> {
+    let code = link.trim().strip_prefix("[&")?.strip_suffix(']')?;
+    let bytes = base64::decode(code).ok()?;
+
+    // Item links start with the link type 2 and a stack count, followed by the
+    // item ID as a 24 bit little endian number.
+    if bytes.len() < 5 || bytes[0] != 2 {
+        return None;
+    }
+
+    return Some(
+        bytes[2] as u32 | (bytes[3] as u32) << 8 | (bytes[4] as u32) << 16
+    );
+}
+
+/// Get the item ID out of an item ID or a chat link. Keys of the descriptions
+/// file and references to ingredients may use either instead of a name.
+pub fn parse_id(item: &str) -> Option<ItemId> {
+    return parse_chat_link(item).or_else(|| item.trim().parse().ok());
+}
+
+/// An item given by name, by ID or by chat link in one of the input files.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ItemReference {
+    Id(ItemId),
+    Text(String)
+}
+
+impl ItemReference {
+    /// Find the ID of the item, if it is described in the `database`.
+    pub fn resolve(&self, database: &Database) -> Option<ItemId> {
+        match self {
+            ItemReference::Id(id) => database.resolve(&id.to_string()),
+            ItemReference::Text(text) => database.resolve(text)
+        }
+    }
+}
+
+impl std::fmt::Display for ItemReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ItemReference::Id(id) => write!(f, "{}", id),
+            ItemReference::Text(text) => write!(f, "{}", text)
+        }
+    }
+}
+
+/// Everything the planner knows about how to get one item, with the
+/// ingredients of its recipes referred to by item ID.
+#[derive(Clone, Debug)]
+pub struct Material {
+    id: ItemId,
+    name: Option<String>,
+    post_id: Option<u32>,
+    vendor: Option<u32>,
+    recipes: Vec<Recipe<ItemId>>
+}
+
+impl Material {
+    pub fn id(&self) -> ItemId {
+        return self.id;
+    }
+
+    /// The name of the item, if it is known.
+    pub fn name(&self) -> &Option<String> {
+        return &self.name;
+    }
+
+    /// The Trading Post ID of the item, if it can be traded.
+    pub fn post_id(&self) -> &Option<u32> {
+        return &self.post_id;
+    }
+
+    /// The price of the item at a vendor, if a vendor sells it.
+    pub fn vendor(&self) -> &Option<u32> {
+        return &self.vendor;
+    }
+
+    /// All the recipes that can craft the item.
+    pub fn recipes(&self) -> &Vec<Recipe<ItemId>> {
+        return &self.recipes;
+    }
+}
+
+/// The material descriptions keyed by item ID, along with an index of item
+/// names.
+#[derive(Clone, Debug, Default)]
+pub struct Database {
+    materials: HashMap<ItemId, Material>,
+    ids: HashMap<String, ItemId>
+}
+
+impl Database {
+    /// Index material descriptions by item ID. Items without an ID, items that
+    /// have the same ID as an earlier item (by name), and recipes with an
+    /// ingredient that is not described are left out. `validate` reports all
+    /// of these.
+    pub fn new(descriptions: &HashMap<String, MaterialDescription>)
+    -> Database {
+        let mut database = Database::default();
+
+        let mut keys: Vec<&String> = descriptions.keys().collect();
+        keys.sort();
+
+        let mut indexed = Vec::<(ItemId, &MaterialDescription)>::new();
+        for key in keys {
+            let description = &descriptions[key];
+            let key_id = parse_id(key);
+            let id = match description.id().or(key_id) {
+                Some(id) => id,
+                None => continue
+            };
+
+            if database.materials.contains_key(&id) {
+                continue;
+            }
+
+            let name = if key_id.is_some() { None } else { Some(key.clone()) };
+            if let Some(name) = &name {
+                database.ids.insert(name.to_lowercase(), id);
+            }
+
+            database.materials.insert(id, Material{
+                id,
+                name,
+                post_id: *description.post_id(),
+                vendor: *description.vendor(),
+                recipes: Vec::new()
+            });
+            indexed.push((id, description));
+        }
+
+        // Ingredients can only be resolved once every item has been indexed.
+        for (id, description) in indexed {
+            let recipes: Vec<Recipe<ItemId>> = description.recipes().iter()
+                .filter_map(|recipe| {
+                    let ingredients: Option<HashMap<ItemId, u32>> =
+                        recipe.ingredients().iter()
+                        .map(|(name, quantity)| {
+                            database.resolve(name).map(|id| (id, *quantity))
+                        })
+                        .collect();
+                    ingredients.map(|i| Recipe::new(recipe.output(), i))
+                })
+                .collect();
+
+            database.materials.get_mut(&id).unwrap().recipes = recipes;
+        }
+
+        return database;
+    }
+
+    /// Find the ID of a described item from its name, its ID or a chat link.
+    /// Names are not case sensitive.
+    pub fn resolve(&self, item: &str) -> Option<ItemId> {
+        let id = parse_id(item).or_else(
+            || self.ids.get(&item.trim().to_lowercase()).copied()
+        )?;
+
+        if self.materials.contains_key(&id) {
+            return Some(id);
+        }
+
+        return None;
+    }
+
+    pub fn get(&self, id: ItemId) -> Option<&Material> {
+        return self.materials.get(&id);
+    }
+
+    pub fn materials(&self) -> &HashMap<ItemId, Material> {
+        return &self.materials;
+    }
+
+    /// The name of an item to show to users. Items without a known name are
+    /// shown by their ID.
+    pub fn name(&self, id: ItemId) -> String {
+        if let Some(Some(name)) = self.materials.get(&id).map(|m| &m.name) {
+            return name.clone();
+        }
+
+        return format!("item {}", id);
+    }
+
+    /// The IDs of the described items whose names are not known.
+    pub fn unnamed(&self) -> Vec<ItemId> {
+        let mut ids: Vec<ItemId> = self.materials.values()
+            .filter(|material| material.name.is_none())
+            .map(|material| material.id)
+            .collect();
+        ids.sort();
+        return ids;
+    }
+
+    /// Give a name to a described item that was only known by its ID.
+    pub fn set_name(&mut self, id: ItemId, name: String) {
+        if let Some(material) = self.materials.get_mut(&id) {
+            if material.name.is_none() {
+                self.ids.entry(name.to_lowercase()).or_insert(id);
+                material.name = Some(name);
+            }
+        }
+    }
+}
@@ -1,101 +1,287 @@
 use std::collections::HashMap;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::path::Path;
 
-#[derive(Clone,Debug)]
-pub struct MaterialDescription {
-    post_id: Option<u32>,
-    vendor: Option<u32>,
-    recipes: Vec<HashMap<String, u32>>
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Unexpected};
+use serde::de::Visitor;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::error::{LoadError, Problem};
+
+/// One way of crafting an item. The descriptions file refers to ingredients
+/// by name, while the planner refers to them by item ID.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Recipe<K: Eq + Hash = String> {
+    output: u32,
+    ingredients: HashMap<K, u32>
 }
 
-fn get_u32_if_available(
-    map: &BTreeMap<String, serde_yaml::Value>,
-    name: &str
-) -> Option<u32> {
+impl<K: Eq + Hash> Recipe<K> {
+    pub fn new(output: u32, ingredients: HashMap<K, u32>) -> Recipe<K> {
+        return Recipe{output, ingredients};
+    }
 
-    if let Some(value) = map.get(name) {
-        match value {
-            serde_yaml::Value::Number(v) => {
-                return Some(v.as_u64().unwrap() as u32);
-            },
-            serde_yaml::Value::Null => {
-                return None;
-             },
-            _ => {
-                panic!("Invalid data type for [{}] field: {:?}. \
-                        Expected an unsigned integer.", name, value);
-            }
-        }
-    } else {
-        return None;
+    /// How many items a single craft produces.
+    pub fn output(&self) -> u32 {
+        return self.output;
+    }
+
+    /// The quantity of each ingredient consumed by a single craft.
+    pub fn ingredients(&self) -> &HashMap<K, u32> {
+        return &self.ingredients;
     }
 }
 
-fn convert_recipe(recipe: &serde_yaml::Value) -> HashMap<String, u32> {
-    match recipe {
-        serde_yaml::Value::Mapping(map) => {
-            return serde_yaml::from_value(
-                serde_yaml::Value::Mapping(map.clone())
-            ).unwrap();
-        },
-        _ => {
-            panic!("Invalid data type for a recipe entry: {:?}. \
-                    Expected a map.", recipe);
-        }
+// The quantity of an ingredient in a recipe, which has to be at least one.
+struct Quantity(u32);
+
+struct QuantityVisitor;
+
+impl<'de> Visitor<'de> for QuantityVisitor {
+    type Value = Quantity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a quantity of at least one")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Quantity, E> {
+        return match u32::try_from(value) {
+            Ok(0) => Err(E::custom("a recipe must use at least one of an \
+                                    ingredient")),
+            Ok(quantity) => Ok(Quantity(quantity)),
+            Err(_) => Err(E::invalid_value(Unexpected::Unsigned(value), &self))
+        };
     }
 }
 
-fn get_recipes(map: &BTreeMap<String, serde_yaml::Value>)
--> Vec<HashMap<String, u32>> {
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+    -> Result<Quantity, D::Error> {
+        return deserializer.deserialize_u32(QuantityVisitor);
+    }
+}
+
+struct RecipeVisitor;
 
-    let mut recipes: Vec<HashMap<String, u32>> = Vec::new();
+impl<'de> Visitor<'de> for RecipeVisitor {
+    type Value = Recipe;
 
-    let recipes_key = "recipes";
-    if let Some(value) = map.get(recipes_key) {
-        match value {
-            serde_yaml::Value::Sequence(seq) => {
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of ingredients, or a map with [ingredients] and \
+                     an optional [output] count")
+    }
 
-                for recipe in seq.iter() {
-                    recipes.push(convert_recipe(recipe));
+    // A recipe is either a plain map of ingredients that produces a single
+    // item per craft, or a map with an [ingredients] entry and an optional
+    // [output] count.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A)
+    -> Result<Recipe, A::Error> {
+        let mut output: Option<u32> = None;
+        let mut ingredients: Option<HashMap<String, u32>> = None;
+        let mut plain = HashMap::<String, u32>::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "output" => {
+                    let value: u32 = map.next_value()?;
+                    if value == 0 {
+                        return Err(de::Error::custom(
+                            "a recipe must output at least one item"
+                        ));
+                    }
+                    output = Some(value);
+                },
+                "ingredients" => {
+                    let value: HashMap<String, Quantity> = map.next_value()?;
+                    ingredients = Some(
+                        value.into_iter().map(|(k, q)| (k, q.0)).collect()
+                    );
+                },
+                _ => {
+                    let value: Quantity = map.next_value()?;
+                    plain.insert(key, value.0);
                 }
+            }
+        }
 
-                return recipes;
-            },
-            serde_yaml::Value::Null => {
-                return recipes;
-            },
-            _ => {
-                panic!("Invalid data type for [{}] field: {:?}. \
-                        Expected a sequence.", recipes_key, value);
+        if let Some(ingredients) = ingredients {
+            if let Some(name) = plain.keys().next() {
+                return Err(de::Error::custom(format!(
+                    "ingredient [{}] is outside of the recipe's [ingredients]",
+                    name
+                )));
             }
+
+            return Ok(Recipe{output: output.unwrap_or(1), ingredients});
         }
-    } else {
-        return recipes;
+
+        return Ok(Recipe{output: output.unwrap_or(1), ingredients: plain});
     }
 }
 
-impl MaterialDescription {
-    pub fn new(info: &serde_yaml::Value) -> MaterialDescription {
+impl<'de> Deserialize<'de> for Recipe {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+    -> Result<Recipe, D::Error> {
+        return deserializer.deserialize_map(RecipeVisitor);
+    }
+}
 
-        let dict: BTreeMap<String, serde_yaml::Value> =
-            serde_yaml::from_value(info.clone()).unwrap();
+impl<K: Eq + Hash + Ord + Serialize> Serialize for Recipe<K> {
+    fn serialize<S: Serializer>(&self, serializer: S)
+    -> Result<S::Ok, S::Error> {
+        let ingredients: BTreeMap<&K, &u32> =
+            self.ingredients.iter().collect();
 
-        return MaterialDescription{
-            post_id: get_u32_if_available(&dict, "post_id"),
-            vendor: get_u32_if_available(&dict, "vendor"),
-            recipes: get_recipes(&dict)
-        };
+        if self.output == 1 {
+            return ingredients.serialize(serializer);
+        }
+
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("ingredients", &ingredients)?;
+        map.serialize_entry("output", &self.output)?;
+        return map.end();
     }
+}
 
+/// Everything the planner knows about how to get one item.
+#[derive(Clone,Debug,Default,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vendor: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recipes: Vec<Recipe>
+}
+
+impl MaterialDescription {
+    pub fn new(
+        id: Option<u32>,
+        post_id: Option<u32>,
+        vendor: Option<u32>,
+        recipes: Vec<Recipe>
+    ) -> MaterialDescription {
+        return MaterialDescription{id, post_id, vendor, recipes};
+    }
+
+    /// The item ID. Items that can be traded default to their `post_id`, so
+    /// only untradable items need to give an `id`.
+    pub fn id(&self) -> Option<u32> {
+      return self.id.or(self.post_id);
+    }
+
+    /// The Trading Post ID of the item, if it can be traded.
     pub fn post_id(&self) -> &Option<u32> {
       return &self.post_id;
     }
 
+    /// The price of the item at a vendor, if a vendor sells it.
     pub fn vendor(&self) -> &Option<u32> {
       return &self.vendor;
     }
 
-    pub fn recipes(&self) -> &Vec<HashMap<String, u32>> {
+    /// All the recipes that can craft the item.
+    pub fn recipes(&self) -> &Vec<Recipe> {
       return &self.recipes;
     }
 }
+
+// Whether a line starts a new top level entry in a block style YAML file.
+pub(crate) fn starts_entry(line: &str) -> bool {
+    return match line.chars().next() {
+        None | Some(' ') | Some('\t') | Some('#') => false,
+        _ => line != "---"
+    };
+}
+
+// Split a block style YAML mapping into its top level entries, so that each
+// entry can be checked on its own and one bad entry does not hide problems
+// in the rest of the file. Every piece is padded with empty lines so that
+// the line numbers in its errors match the whole file.
+fn split_entries(source: &str) -> Vec<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let starts: Vec<usize> = (0..lines.len())
+        .filter(|i| starts_entry(lines[*i]))
+        .collect();
+
+    let block_mapping = starts.iter().all(
+        |i| !lines[*i].starts_with('{') && !lines[*i].starts_with('-')
+    );
+    if !block_mapping || starts.is_empty() {
+        return vec![source.to_string()];
+    }
+
+    let mut entries = Vec::<String>::new();
+    for (index, start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or(lines.len());
+        entries.push(
+            "\n".repeat(*start) + &lines[*start..end].join("\n")
+        );
+    }
+
+    return entries;
+}
+
+/// Parse a material descriptions database, keyed by item name. Every problem
+/// in the source is reported, each with its key path and location.
+pub fn parse(source: &str) -> Result<HashMap<String, MaterialDescription>, Vec<Problem>> {
+    let mut descriptions = HashMap::<String, MaterialDescription>::new();
+    let mut problems = Vec::<Problem>::new();
+
+    for entry in split_entries(source) {
+        let deserializer = serde_yaml::Deserializer::from_str(&entry);
+        let parsed: Result<Option<BTreeMap<String, Option<MaterialDescription>>>, _> =
+            serde_path_to_error::deserialize(deserializer);
+
+        match parsed {
+            Ok(parsed) => {
+                for (name, description) in parsed.unwrap_or_default() {
+                    if descriptions.contains_key(&name) {
+                        problems.push(Problem::new(
+                            name.clone(),
+                            None,
+                            None,
+                            "the item is described more than once".to_string()
+                        ));
+                    }
+
+                    descriptions.insert(name, description.unwrap_or_default());
+                }
+            },
+            Err(error) => {
+                problems.push(Problem::from_yaml(error));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(descriptions);
+    }
+
+    return Err(problems);
+}
+
+/// Load a material descriptions database, keyed by item name.
+pub fn load_file(path: &Path)
+-> Result<HashMap<String, MaterialDescription>, LoadError> {
+    let source = std::fs::read_to_string(path).map_err(
+        |error| LoadError::Io{file: path.to_path_buf(), error}
+    )?;
+
+    return parse(&source).map_err(
+        |problems| LoadError::Invalid{file: path.to_path_buf(), problems}
+    );
+}
+
+/// Write a material descriptions database as YAML, sorted by item name.
+pub fn to_yaml(descriptions: &HashMap<String, MaterialDescription>) -> String {
+    let sorted: BTreeMap<&String, &MaterialDescription> =
+        descriptions.iter().collect();
+    return serde_yaml::to_string(&sorted).unwrap();
+}
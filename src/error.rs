@@ -0,0 +1,127 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// A single problem found while loading a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    path: String,
+    line: Option<usize>,
+    column: Option<usize>,
+    message: String
+}
+
+impl Problem {
+    pub fn new(
+        path: String,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String
+    ) -> Problem {
+        return Problem{path, line, column, message};
+    }
+
+    /// Build a problem out of an error reported while deserializing YAML.
+    pub fn from_yaml(
+        error: serde_path_to_error::Error<serde_yaml::Error>
+    ) -> Problem {
+        let path = error.path().to_string();
+        let inner = error.into_inner();
+        let location = inner.location();
+
+        // serde_yaml puts the key path and location into its messages, but
+        // those are reported separately here.
+        let mut message = inner.to_string();
+        if let Some(end) = message.find(": ") {
+            // Its paths into a list start with a dot, e.g. [.[0].must].
+            if path.starts_with(message[..end].trim_start_matches('.')) {
+                message = message[end + 2..].to_string();
+            }
+        }
+        if location.is_some() {
+            if let Some(end) = message.rfind(" at line ") {
+                message.truncate(end);
+            }
+        }
+
+        return Problem{
+            path: if path == "." { String::new() } else { path },
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            message
+        };
+    }
+
+    /// The key path to the value with the problem, e.g. [iron ingot.vendor].
+    pub fn path(&self) -> &String {
+        return &self.path;
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        return self.line;
+    }
+
+    pub fn column(&self) -> Option<usize> {
+        return self.column;
+    }
+
+    pub fn message(&self) -> &String {
+        return &self.message;
+    }
+}
+
+/// An error from loading one of the YAML input files.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read.
+    Io {
+        file: PathBuf,
+        error: std::io::Error
+    },
+    /// The file was read but its contents are invalid.
+    Invalid {
+        file: PathBuf,
+        problems: Vec<Problem>
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io{file, error} => {
+                write!(f, "{}: {}", file.display(), error)
+            },
+            LoadError::Invalid{file, problems} => {
+                for (index, problem) in problems.iter().enumerate() {
+                    if index > 0 {
+                        writeln!(f)?;
+                    }
+
+                    write!(f, "{}", file.display())?;
+                    if let Some(line) = problem.line {
+                        write!(f, ":{}", line)?;
+                        if let Some(column) = problem.column {
+                            write!(f, ":{}", column)?;
+                        }
+                    }
+
+                    if problem.path.is_empty() {
+                        write!(f, ": {}", problem.message)?;
+                    } else {
+                        write!(f, ": [{}] {}", problem.path, problem.message)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io{error, ..} => Some(error),
+            LoadError::Invalid{..} => None
+        }
+    }
+}
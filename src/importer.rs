@@ -0,0 +1,153 @@
+use crate::descriptions::{MaterialDescription, Recipe};
+use crate::tp_rest::{self, Error, ItemInfo, RecipeInfo};
+
+use std::collections::{HashMap, HashSet};
+
+use restson::RestClient;
+
+fn merge(existing: &MaterialDescription, imported: MaterialDescription)
+-> MaterialDescription {
+    // Hand written descriptions take priority over the API, which does not
+    // know about vendor prices.
+    let mut recipes = existing.recipes().clone();
+    for recipe in imported.recipes() {
+        if !recipes.contains(recipe) {
+            recipes.push(recipe.clone());
+        }
+    }
+
+    let post_id = existing.post_id().or(*imported.post_id());
+    let id = existing.id().or(imported.id()).filter(|id| Some(*id) != post_id);
+
+    return MaterialDescription::new(
+        id,
+        post_id,
+        *existing.vendor(),
+        recipes
+    );
+}
+
+// The key of each item in the descriptions, which is its lowercased name.
+// Different items can have the same name, and an item can have the name of
+// an existing description of another item, so those are keyed by their ID
+// instead of being merged.
+fn keys(
+    items: &[ItemInfo],
+    descriptions: &HashMap<String, MaterialDescription>
+) -> HashMap<u32, String> {
+    let mut uses = HashMap::<String, u32>::new();
+    for item in items {
+        *uses.entry(item.name().to_lowercase()).or_insert(0) += 1;
+    }
+
+    return items.iter()
+        .map(|item| {
+            let name = item.name().to_lowercase();
+            let taken = descriptions.get(&name)
+                .and_then(|existing| existing.id())
+                .is_some_and(|id| id != item.id());
+            if 1 < uses[&name] || taken {
+                (item.id(), item.id().to_string())
+            } else {
+                (item.id(), name)
+            }
+        })
+        .collect();
+}
+
+/// Import the descriptions of the `targets` and of every ingredient in their
+/// recipes, all the way down, from the recipes and items API at `api_url`.
+/// Items that are already in `descriptions` keep their post_id and vendor
+/// price, and gain any recipes they were missing. Item names are lowercased to
+/// match the descriptions file, and items that share a name with another item
+/// are keyed by their ID instead. Returns the keys of the items that were not
+/// described before.
+pub fn import(
+    api_url: &str,
+    targets: &[u32],
+    descriptions: &mut HashMap<String, MaterialDescription>
+) -> Result<Vec<String>, Error> {
+    let mut client = tp_rest::connect(api_url)?;
+    return import_with(&mut client, targets, descriptions);
+}
+
+/// Same as [import] but using an existing REST client.
+pub fn import_with(
+    client: &mut RestClient,
+    targets: &[u32],
+    descriptions: &mut HashMap<String, MaterialDescription>
+) -> Result<Vec<String>, Error> {
+    let mut visited: HashSet<u32> = targets.iter().copied().collect();
+    let mut pending: Vec<u32> = visited.iter().copied().collect();
+    let mut recipes = HashMap::<u32, Vec<RecipeInfo>>::new();
+
+    while !pending.is_empty() {
+        let mut recipe_ids = Vec::<u32>::new();
+        for id in pending.drain(..) {
+            recipe_ids.extend(tp_rest::search_recipes(client, id)?);
+        }
+
+        for recipe in tp_rest::get_recipes(client, &recipe_ids)? {
+            // Recipes that need currencies or guild upgrades cannot be
+            // planned for, so they are left out.
+            if !recipe.ingredients().iter().all(|i| i.is_item()) {
+                continue;
+            }
+
+            for ingredient in recipe.ingredients() {
+                if visited.insert(ingredient.id()) {
+                    pending.push(ingredient.id());
+                }
+            }
+
+            recipes.entry(recipe.output_item_id()).or_default().push(recipe);
+        }
+    }
+
+    let mut ids: Vec<u32> = visited.into_iter().collect();
+    ids.sort();
+
+    let items = tp_rest::get_items(client, &ids)?;
+    let names = keys(&items, descriptions);
+
+    let mut added = Vec::<String>::new();
+    for item in &items {
+        let mut item_recipes = Vec::<Recipe>::new();
+        for recipe in recipes.get(&item.id()).unwrap_or(&Vec::new()) {
+            let ingredients: Option<HashMap<String, u32>> =
+                recipe.ingredients().iter()
+                .map(|i| names.get(&i.id()).map(|name| (name.clone(), i.count())))
+                .collect();
+
+            // Skip any recipe with an ingredient that the items API does not
+            // know about.
+            if let Some(ingredients) = ingredients {
+                let recipe = Recipe::new(recipe.output_item_count(), ingredients);
+                if !item_recipes.contains(&recipe) {
+                    item_recipes.push(recipe);
+                }
+            }
+        }
+
+        // Tradable items are identified by their post_id, so only untradable
+        // items need an explicit id.
+        let imported = MaterialDescription::new(
+            if item.is_tradable() { None } else { Some(item.id()) },
+            if item.is_tradable() { Some(item.id()) } else { None },
+            None,
+            item_recipes
+        );
+
+        let name = &names[&item.id()];
+        if let Some(existing) = descriptions.get(name) {
+            let merged = merge(existing, imported);
+            descriptions.insert(name.clone(), merged);
+        } else {
+            descriptions.insert(name.clone(), imported);
+            added.push(name.clone());
+        }
+    }
+
+    added.sort();
+    return Ok(added);
+}
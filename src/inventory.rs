@@ -0,0 +1,177 @@
+use crate::database::ItemId;
+use crate::profit;
+use crate::tp_rest::{self, Error, Item, ItemStack};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Where in an account some owned items are kept.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Location {
+    MaterialStorage,
+    Bank,
+    SharedInventory,
+    /// The bags of the character with this name.
+    Character(String)
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::MaterialStorage => write!(f, "the material storage"),
+            Location::Bank => write!(f, "the bank"),
+            Location::SharedInventory => write!(f, "the shared inventory"),
+            Location::Character(name) => write!(f, "{}'s bags", name)
+        }
+    }
+}
+
+/// What using an owned item costs the plan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Valuation {
+    /// Owned items are free.
+    Free,
+    /// Owned items cost what they would sell for to the highest buy order,
+    /// after the Trading Post fees, since using them gives that up.
+    SellValue,
+    /// Owned items cost what it would take to buy them back instantly.
+    InstantBuy
+}
+
+impl Valuation {
+    /// What one of an item is worth, given its Trading Post order book.
+    /// Items that cannot be traded are free.
+    pub fn unit_value(&self, listings: Option<&Item>) -> u32 {
+        let item = match listings {
+            Some(item) => item,
+            None => return 0
+        };
+
+        return match self {
+            Valuation::Free => 0,
+            Valuation::SellValue => profit::sell_to_buy_orders(item, 1)
+                .map(|sale| sale.net())
+                .unwrap_or(0),
+            Valuation::InstantBuy => item.sells().first()
+                .map(|price| price.unit_price())
+                .unwrap_or(0)
+        };
+    }
+}
+
+impl std::str::FromStr for Valuation {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Valuation, String> {
+        match name {
+            "free" => Ok(Valuation::Free),
+            "sell" => Ok(Valuation::SellValue),
+            "buy" => Ok(Valuation::InstantBuy),
+            _ => Err(format!("Unknown valuation [{}]", name))
+        }
+    }
+}
+
+/// Some quantity of an item taken out of one location.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Withdrawal {
+    location: Location,
+    quantity: u32
+}
+
+impl Withdrawal {
+    pub fn location(&self) -> &Location {
+        return &self.location;
+    }
+
+    pub fn quantity(&self) -> u32 {
+        return self.quantity;
+    }
+}
+
+/// The items that an account already owns, and where they are kept.
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    stacks: HashMap<ItemId, Vec<(Location, u32)>>
+}
+
+impl Inventory {
+    /// Add `quantity` of an `item` kept at `location`.
+    pub fn add(&mut self, item: ItemId, location: Location, quantity: u32) {
+        let stacks = self.stacks.entry(item).or_default();
+        match stacks.iter_mut().find(|(l, _)| *l == location) {
+            Some((_, stored)) => *stored += quantity,
+            None => stacks.push((location, quantity))
+        }
+    }
+
+    fn add_stacks(&mut self, stacks: Vec<ItemStack>, location: &Location) {
+        for stack in stacks {
+            self.add(stack.id(), location.clone(), stack.count());
+        }
+    }
+
+    /// How many of an `item` are owned in total.
+    pub fn quantity(&self, item: ItemId) -> u32 {
+        return self.stacks.get(&item)
+            .map(|stacks| stacks.iter().map(|(_, quantity)| quantity).sum())
+            .unwrap_or(0);
+    }
+
+    /// The total quantity owned of every item.
+    pub fn quantities(&self) -> HashMap<ItemId, u32> {
+        return self.stacks.keys()
+            .map(|item| (*item, self.quantity(*item)))
+            .collect();
+    }
+
+    /// Where to take `quantity` of an `item` from, starting with material
+    /// storage, then the bank, the shared inventory and the characters.
+    pub fn withdraw(&self, item: ItemId, quantity: u32) -> Vec<Withdrawal> {
+        let mut withdrawals = Vec::<Withdrawal>::new();
+        let mut needed = quantity;
+        for (location, stored) in self.stacks.get(&item).into_iter().flatten() {
+            if needed == 0 {
+                break;
+            }
+
+            let taken = std::cmp::min(needed, *stored);
+            withdrawals.push(
+                Withdrawal{location: location.clone(), quantity: taken}
+            );
+            needed -= taken;
+        }
+
+        return withdrawals;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.stacks.is_empty();
+    }
+}
+
+/// Fetch everything that the account of `api_key` keeps in its material
+/// storage, bank, shared inventory slots and character bags. The key needs
+/// the `account`, `inventories` and `characters` permissions.
+pub fn fetch(api_url: &str, api_key: &str) -> Result<Inventory, Error> {
+    let mut client = tp_rest::connect_with_key(api_url, api_key)?;
+
+    let mut inventory = Inventory::default();
+    inventory.add_stacks(
+        tp_rest::get_material_storage(&mut client)?,
+        &Location::MaterialStorage
+    );
+    inventory.add_stacks(tp_rest::get_bank(&mut client)?, &Location::Bank);
+    inventory.add_stacks(
+        tp_rest::get_shared_inventory(&mut client)?,
+        &Location::SharedInventory
+    );
+
+    for name in tp_rest::get_characters(&mut client)? {
+        let stacks = tp_rest::get_character_inventory(&mut client, &name)?;
+        inventory.add_stacks(stacks, &Location::Character(name));
+    }
+
+    return Ok(inventory);
+}
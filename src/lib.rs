@@ -0,0 +1,55 @@
+//! Plan the cheapest way to craft items in Guild Wars 2.
+//!
+//! The planner combines a database of material descriptions (which items can
+//! be bought on the Trading Post, bought from vendors, or crafted from
+//! recipes) with the current Trading Post listings, and searches for the
+//! cheapest combination of purchases and crafts for a list of targets.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let descriptions = gw2_tp::descriptions::load_file(
+//!     Path::new(".material-descriptions.yaml")
+//! ).unwrap();
+//! let database = gw2_tp::database::Database::new(&descriptions);
+//! let targets = gw2_tp::targets::load_file(
+//!     Path::new("material-targets.yaml"), &database
+//! ).unwrap();
+//!
+//! let listings = gw2_tp::tp_rest::get_listings_for_targets(
+//!     gw2_tp::tp_rest::API_URL,
+//!     targets.iter().map(|t| t.item()).collect(),
+//!     &database
+//! ).into_listings();
+//!
+//! let inventory = gw2_tp::inventory::Inventory::default();
+//! if let Ok(plan) = gw2_tp::planning::plan(
+//!     &targets,
+//!     &database,
+//!     &listings,
+//!     &inventory,
+//!     &gw2_tp::planning::Options::default()
+//! ) {
+//!     println!("Total cost: {}", plan.total_cost());
+//! }
+//! ```
+
+#![allow(clippy::needless_return)]
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod cache;
+pub mod database;
+pub mod descriptions;
+pub mod error;
+pub mod importer;
+pub mod inventory;
+pub mod market;
+pub mod orders;
+pub mod planning;
+pub mod profit;
+pub mod report;
+pub mod targets;
+pub mod tp_rest;
+pub mod validate;
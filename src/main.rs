@@ -1,25 +1,76 @@
-#[macro_use]
-extern crate serde_derive;
+extern crate argparse;
+use argparse::{ArgumentParser, List, Store, StoreTrue};
 
-use std::collections::HashMap;
+use gw2_tp::{
+    cache, database, descriptions, importer, inventory, market, orders,
+    planning, profit, report, targets, tp_rest, validate
+};
+use gw2_tp::market::MarketSource;
 
-mod descriptions;
-mod tp_rest;
-mod planning;
+enum Command {
+    Plan,
+    Profit,
+    Validate,
+    Import
+}
 
-use descriptions::MaterialDescription;
-
-extern crate argparse;
-use argparse::{ArgumentParser, Store};
+impl std::str::FromStr for Command {
+    type Err = String;
 
+    fn from_str(name: &str) -> Result<Command, String> {
+        match name {
+            "plan" => Ok(Command::Plan),
+            "profit" => Ok(Command::Profit),
+            "validate" => Ok(Command::Validate),
+            "import" => Ok(Command::Import),
+            _ => Err(format!("Unknown command [{}]", name))
+        }
+    }
+}
 
 fn main() {
 
+    let mut command = Command::Plan;
+
+    let mut arguments = Vec::<String>::new();
+
+    let mut output_file_name = String::new();
+
     let mut material_descriptions_file_name =
         ".material-descriptions.yaml".to_string();
 
     let mut target_materials_file_name = "material-targets.yaml".to_string();
 
+    let mut print_yaml = false;
+
+    let mut api_url = tp_rest::API_URL.to_string();
+
+    let mut cache_file_name = cache::CACHE_FILE.to_string();
+
+    let mut ttl = cache::DEFAULT_TTL.as_secs();
+
+    let mut offline = false;
+
+    let mut market_name = "listings".to_string();
+
+    let mut price_file_name = String::new();
+
+    let mut buy_orders = false;
+
+    let mut order_offset = orders::DEFAULT_OFFSET;
+
+    let mut api_key = std::env::var("GW2_API_KEY").unwrap_or_default();
+
+    let mut valuation = inventory::Valuation::SellValue;
+
+    let mut credit_leftovers = false;
+
+    let mut node_limit = 0usize;
+
+    let mut time_limit = 0u64;
+
+    let mut memory_limit = 0usize;
+
     {
         let mut parser = ArgumentParser::new();
         parser.set_description(
@@ -43,53 +94,446 @@ fn main() {
                 "Target materials list"
             );
 
+        parser
+            .refer(&mut print_yaml)
+            .add_option(
+                &["--yaml"],
+                StoreTrue,
+                "Print the plan as YAML instead of a report"
+            );
+
+        parser
+            .refer(&mut api_url)
+            .add_option(
+                &["--api"],
+                Store,
+                "Base URL of the Guild Wars 2 API"
+            );
+
+        parser
+            .refer(&mut cache_file_name)
+            .add_option(
+                &["--cache"],
+                Store,
+                "Where to cache the Trading Post listings"
+            );
+
+        parser
+            .refer(&mut ttl)
+            .add_option(
+                &["--ttl"],
+                Store,
+                "How many seconds to use cached listings before fetching them \
+                again"
+            );
+
+        parser
+            .refer(&mut offline)
+            .add_option(
+                &["--offline"],
+                StoreTrue,
+                "Plan with the cached listings only, however old they are"
+            );
+
+        parser
+            .refer(&mut market_name)
+            .add_option(
+                &["--market"],
+                Store,
+                "Plan with the full order books from the [listings] API \
+                (default), or only the best prices from the [prices] API"
+            );
+
+        parser
+            .refer(&mut price_file_name)
+            .add_option(
+                &["--prices"],
+                Store,
+                "Plan with the prices in a CSV or JSON file instead of the API"
+            );
+
+        parser
+            .refer(&mut buy_orders)
+            .add_option(
+                &["--buy-orders"],
+                StoreTrue,
+                "Plan to buy Trading Post materials with buy orders instead of \
+                buying them instantly"
+            );
+
+        parser
+            .refer(&mut order_offset)
+            .add_option(
+                &["--order-offset"],
+                Store,
+                "How far above the highest buy order to place buy orders"
+            );
+
+        parser
+            .refer(&mut api_key)
+            .add_option(
+                &["--api-key"],
+                Store,
+                "API key of an account whose materials are used before \
+                acquiring anything, instead of the GW2_API_KEY variable"
+            );
+
+        parser
+            .refer(&mut valuation)
+            .add_option(
+                &["--owned-value"],
+                Store,
+                "What using an owned item costs: what it would [sell] for to \
+                buy orders after fees (default), what it would cost to [buy] \
+                instantly, or nothing if it is [free]"
+            );
+
+        parser
+            .refer(&mut credit_leftovers)
+            .add_option(
+                &["--credit-leftovers"],
+                StoreTrue,
+                "Count what the leftovers of a plan would sell for against its \
+                cost when choosing between recipes"
+            );
+
+        parser
+            .refer(&mut node_limit)
+            .add_option(
+                &["--max-nodes"],
+                Store,
+                "Stop searching after this many search nodes and use the best \
+                plan found so far"
+            );
+
+        parser
+            .refer(&mut time_limit)
+            .add_option(
+                &["--time-limit"],
+                Store,
+                "Stop searching after this many seconds and use the best plan \
+                found so far"
+            );
+
+        parser
+            .refer(&mut memory_limit)
+            .add_option(
+                &["--max-memory"],
+                Store,
+                "Stop searching once the search takes up roughly this many \
+                megabytes and use the best plan found so far"
+            );
+
+        parser
+            .refer(&mut command)
+            .add_argument(
+                "command",
+                Store,
+                "[plan] the targets (default), work out the [profit] of \
+                crafting and selling them, [validate] the material \
+                descriptions, or [import] descriptions of the item IDs given \
+                as arguments from the API"
+            );
+
+        parser
+            .refer(&mut arguments)
+            .add_argument(
+                "arguments",
+                List,
+                "Arguments for the command"
+            );
+
+        parser
+            .refer(&mut output_file_name)
+            .add_option(
+                &["-o", "--output"],
+                Store,
+                "Where [import] writes the descriptions, instead of stdout"
+            );
+
         parser.parse_args_or_exit();
     }
 
-    let material_descriptions_file =
-        std::fs::File::open(
-            std::path::Path::new(&material_descriptions_file_name)
-        ).unwrap();
+    if let Command::Import = command {
+        import(
+            &api_url,
+            &material_descriptions_file_name,
+            &arguments,
+            &output_file_name
+        );
+        return;
+    }
+
+    let descriptions = match descriptions::load_file(
+        std::path::Path::new(&material_descriptions_file_name)
+    ) {
+        Ok(descriptions) => descriptions,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let issues = validate::validate(&descriptions);
+    if let Command::Validate = command {
+        for issue in &issues {
+            println!("{}", issue);
+        }
 
-    let descriptions_yaml: serde_yaml::Mapping =
-        serde_yaml::from_reader(material_descriptions_file).unwrap();
+        if issues.is_empty() {
+            println!("No problems found in {}", material_descriptions_file_name);
+            return;
+        }
 
-    let mut descriptions = HashMap::<String, MaterialDescription>::new();
-    for (material, description) in &descriptions_yaml {
-        descriptions.insert(
-            material.as_str().unwrap().to_string(),
-            MaterialDescription::new(&description)
+        println!(
+            "Found {} problems in {}",
+            issues.len(), material_descriptions_file_name
         );
+        std::process::exit(1);
+    }
+
+    for issue in &issues {
+        eprintln!("Warning: {}", issue);
+    }
+
+    let mut database = database::Database::new(&descriptions);
+    if !offline {
+        if let Err(error) = tp_rest::fetch_names(&api_url, &mut database) {
+            eprintln!("Warning: Failed to look up item names: {}", error);
+        }
+    }
+
+    let target_materials = match targets::load_file(
+        std::path::Path::new(&target_materials_file_name),
+        &database
+    ) {
+        Ok(target_materials) => match command {
+            Command::Profit => {
+                profit::craft_targets(&target_materials, &database)
+            },
+            _ => target_materials
+        },
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // With --yaml, the plan is the only thing printed to stdout, so that the
+    // output can be parsed as it is.
+    if !print_yaml {
+        println!("Target materials:");
+        for target in &target_materials {
+            println!("{}", target);
+        }
     }
 
-    let target_materials: Vec<String> =
-        serde_yaml::from_reader(
-            std::fs::File::open(
-                std::path::Path::new(&target_materials_file_name)
-            ).unwrap()
-        ).unwrap();
+    let mut source: Box<dyn MarketSource> =
+        if !price_file_name.is_empty() {
+            match market::PriceFile::load(
+                std::path::Path::new(&price_file_name),
+                &database
+            ) {
+                Ok(price_file) => Box::new(price_file),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        } else if market_name == "prices" {
+            if offline {
+                eprintln!(
+                    "--offline plans from the cached listings, and cannot be \
+                    used with --market prices"
+                );
+                std::process::exit(1);
+            }
 
-    println!("Target materials: {:?}", target_materials);
+            Box::new(market::TopOfBook::new(&api_url))
+        } else if market_name == "listings" {
+            let listing_cache = match cache::ListingCache::load(
+                std::path::Path::new(&cache_file_name)
+            ) {
+                Ok(listing_cache) => listing_cache,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
 
-    println!("Parsed result:\n{:?}", descriptions);
+            if offline {
+                Box::new(market::Snapshot::new(listing_cache))
+            } else {
+                Box::new(market::CachedListings::new(
+                    listing_cache,
+                    &api_url,
+                    std::time::Duration::from_secs(ttl)
+                ))
+            }
+        } else {
+            eprintln!("Unknown market [{}]", market_name);
+            std::process::exit(1);
+        };
 
-    let listings = tp_rest::get_listings_for_targets(
-        target_materials.clone(),
-        &descriptions
+    let items = tp_rest::tradable_items(
+        target_materials.iter().map(|t| t.item()).collect(),
+        &database
     );
 
-    println!("\nPrices:");
-    for (name, item) in &listings {
-        println!(
-            "{} lowest price: {}",
-            name,
-            item.sells().get(0).unwrap().unit_price()
+    let listings = source.listings(&database, &items);
+    for error in listings.errors() {
+        eprintln!("Warning: Failed to get Trading Post listings: {}", error);
+    }
+
+    let mut missing: Vec<String> = items.iter()
+        .filter(|item| !listings.listings().contains_key(item))
+        .map(|item| database.name(*item))
+        .collect();
+    missing.sort();
+    for name in missing {
+        eprintln!("Warning: No listings for [{}]", name);
+    }
+
+    if let Err(error) = source.save() {
+        eprintln!("Warning: Failed to save {}: {}", cache_file_name, error);
+    }
+
+    let listings = listings.into_listings();
+
+    if !print_yaml {
+        report::print_prices(&listings, source.recorded(&items), &database);
+    }
+
+    let owned =
+        if api_key.is_empty() || offline {
+            inventory::Inventory::default()
+        } else {
+            match inventory::fetch(&api_url, &api_key) {
+                Ok(owned) => owned,
+                Err(error) => {
+                    eprintln!(
+                        "Warning: Failed to get the account's items, so the \
+                        plan assumes it owns nothing: {}",
+                        error
+                    );
+                    inventory::Inventory::default()
+                }
+            }
+        };
+
+    let prices =
+        if buy_orders {
+            orders::with_buy_orders(&listings, order_offset)
+        } else {
+            listings.clone()
+        };
+
+    let mut options = planning::Options::default()
+        .with_valuation(valuation)
+        .with_leftover_credit(credit_leftovers);
+    if 0 < node_limit {
+        options = options.with_node_limit(node_limit);
+    }
+    if 0 < time_limit {
+        options = options.with_time_limit(
+            std::time::Duration::from_secs(time_limit)
         );
     }
+    if 0 < memory_limit {
+        options = options.with_memory_limit(memory_limit * 1024 * 1024);
+    }
+
+    let plan = planning::plan(
+        &target_materials, &database, &prices, &owned, &options
+    );
+    match plan {
+        Ok(plan) => {
+            let profits = match command {
+                Command::Profit => Some(profit::evaluate(&plan, &listings)),
+                _ => None
+            };
+
+            if print_yaml {
+                match profits {
+                    Some(profits) => print!(
+                        "{}", serde_yaml::to_string(&profits).unwrap()
+                    ),
+                    None => print!(
+                        "{}", serde_yaml::to_string(&plan).unwrap()
+                    )
+                }
+            } else {
+                report::print_plan(&plan, &database);
+                if buy_orders {
+                    report::print_buy_orders(&plan, &listings, order_offset);
+                }
+
+                if let Some(profits) = profits {
+                    report::print_profits(&profits);
+                }
+            }
+        },
+        Err(infeasible) => {
+            eprintln!("No plan could be found for the targets: {}", infeasible);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn import(
+    api_url: &str,
+    material_descriptions_file_name: &str,
+    arguments: &[String],
+    output_file_name: &str
+) {
+    let mut ids = Vec::<u32>::new();
+    for argument in arguments {
+        match argument.parse::<u32>() {
+            Ok(id) => ids.push(id),
+            Err(_) => {
+                eprintln!("[{}] is not an item ID", argument);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    for target in &target_materials {
-        planning::plan_isolated(target, descriptions.clone(), &listings);
+    if ids.is_empty() {
+        eprintln!("Give the IDs of the items to import");
+        std::process::exit(1);
     }
 
-    // planning::plan(&target_materials, descriptions, &listings);
+    // Extend the existing descriptions if there are any
+    let path = std::path::Path::new(material_descriptions_file_name);
+    let mut descriptions =
+        if path.exists() {
+            match descriptions::load_file(path) {
+                Ok(descriptions) => descriptions,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            std::collections::HashMap::new()
+        };
+
+    match importer::import(api_url, &ids, &mut descriptions) {
+        Ok(added) => {
+            for name in &added {
+                eprintln!("Imported [{}]", name);
+            }
+        },
+        Err(error) => {
+            eprintln!("Failed to import from the API: {}", error);
+            std::process::exit(1);
+        }
+    }
+
+    let yaml = descriptions::to_yaml(&descriptions);
+    if output_file_name.is_empty() {
+        print!("{}", yaml);
+    } else if let Err(error) = std::fs::write(output_file_name, yaml) {
+        eprintln!("{}: {}", output_file_name, error);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,328 @@
+use crate::cache::ListingCache;
+use crate::database::{Database, ItemId, ItemReference};
+use crate::error::{LoadError, Problem};
+use crate::tp_rest::{self, Item, Listings, Price};
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Somewhere to get Trading Post order books from. The planner only sees the
+/// order books, so it does not matter where they come from.
+pub trait MarketSource {
+    /// Get the order books of the `items`, keyed by item ID. Items that the
+    /// source knows nothing about are left out.
+    fn listings(&mut self, database: &Database, items: &HashSet<ItemId>)
+    -> Listings;
+
+    /// When the oldest order book among the `items` was recorded, if that is
+    /// known.
+    fn recorded(&self, _items: &HashSet<ItemId>) -> Option<SystemTime> {
+        return None;
+    }
+
+    /// Keep anything that the source needs for the next run.
+    fn save(&self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+/// The full order books from the listings API.
+#[derive(Clone, Debug)]
+pub struct LiveListings {
+    api_url: String
+}
+
+impl LiveListings {
+    pub fn new(api_url: &str) -> LiveListings {
+        return LiveListings{api_url: api_url.to_string()};
+    }
+}
+
+impl MarketSource for LiveListings {
+    fn listings(&mut self, database: &Database, items: &HashSet<ItemId>)
+    -> Listings {
+        return tp_rest::get_listings(&self.api_url, database, items);
+    }
+}
+
+/// Only the best buy and sell price of each item from the prices API. Every
+/// order is treated as if it were at the best price.
+#[derive(Clone, Debug)]
+pub struct TopOfBook {
+    api_url: String
+}
+
+impl TopOfBook {
+    pub fn new(api_url: &str) -> TopOfBook {
+        return TopOfBook{api_url: api_url.to_string()};
+    }
+}
+
+impl MarketSource for TopOfBook {
+    fn listings(&mut self, database: &Database, items: &HashSet<ItemId>)
+    -> Listings {
+        return tp_rest::get_prices(&self.api_url, database, items);
+    }
+}
+
+/// Order books from the listings API that are cached, and only fetched again
+/// once they are older than the time-to-live.
+#[derive(Clone, Debug)]
+pub struct CachedListings {
+    cache: ListingCache,
+    api_url: String,
+    ttl: Duration
+}
+
+impl CachedListings {
+    pub fn new(cache: ListingCache, api_url: &str, ttl: Duration)
+    -> CachedListings {
+        return CachedListings{cache, api_url: api_url.to_string(), ttl};
+    }
+}
+
+impl MarketSource for CachedListings {
+    fn listings(&mut self, database: &Database, items: &HashSet<ItemId>)
+    -> Listings {
+        return self.cache.get_listings(
+            &self.api_url, database, items, self.ttl
+        );
+    }
+
+    fn recorded(&self, items: &HashSet<ItemId>) -> Option<SystemTime> {
+        return self.cache.oldest(items);
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        return self.cache.save();
+    }
+}
+
+/// The order books in a cache, however old they are, without using the
+/// network.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    cache: ListingCache
+}
+
+impl Snapshot {
+    pub fn new(cache: ListingCache) -> Snapshot {
+        return Snapshot{cache};
+    }
+}
+
+impl MarketSource for Snapshot {
+    fn listings(&mut self, _database: &Database, items: &HashSet<ItemId>)
+    -> Listings {
+        return Listings::new(self.cache.listings(items));
+    }
+
+    fn recorded(&self, items: &HashSet<ItemId>) -> Option<SystemTime> {
+        return self.cache.oldest(items);
+    }
+}
+
+// A price given by hand. Either price may be left out, and a missing quantity
+// means that any quantity can be traded at that price.
+#[derive(Deserialize)]
+struct PriceEntry {
+    item: ItemReference,
+    #[serde(default)]
+    sell: Option<u32>,
+    #[serde(default)]
+    buy: Option<u32>,
+    #[serde(default)]
+    quantity: Option<u32>
+}
+
+impl PriceEntry {
+    fn into_item(self, id: ItemId) -> Item {
+        let quantity = self.quantity.unwrap_or(u32::MAX);
+        let tier = |price: Option<u32>| -> Vec<Price> {
+            return price.map(|p| Price::new(0, p, quantity))
+                .into_iter().collect();
+        };
+
+        return Item::new(id, tier(self.buy), tier(self.sell));
+    }
+}
+
+// JSON price files may also hold order books as the listings API gives them,
+// e.g. to plan with historical data.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonEntry {
+    Listings(Item),
+    Price(PriceEntry)
+}
+
+/// Prices from a CSV or JSON file, e.g. to plan with manual or historical
+/// prices.
+///
+/// A CSV file has a header row with an `item` column, and `sell`, `buy` and
+/// `quantity` columns that may be left empty:
+///
+/// ```text
+/// item,sell,buy,quantity
+/// iron ore,40,35,
+/// 19702,90,,250
+/// ```
+///
+/// A JSON file is a list of objects with the same fields, or of order books
+/// as the listings API gives them. Items are given by name, ID or chat link.
+#[derive(Clone, Debug)]
+pub struct PriceFile {
+    listings: HashMap<ItemId, Item>,
+    modified: Option<SystemTime>
+}
+
+fn csv_problem(error: &csv::Error) -> Problem {
+    return Problem::new(
+        String::new(),
+        error.position().map(|position| position.line() as usize),
+        None,
+        error.to_string()
+    );
+}
+
+fn unknown_item(path: String, line: Option<usize>, item: &ItemReference)
+-> Problem {
+    return Problem::new(
+        path, line, None, format!("no described item matches {}", item)
+    );
+}
+
+impl PriceFile {
+    /// Load a price file. Files ending in [.csv] are read as CSV, and any
+    /// other file as JSON. Every item must be described in the `database`.
+    pub fn load(path: &Path, database: &Database)
+    -> Result<PriceFile, LoadError> {
+        let source = std::fs::read_to_string(path).map_err(
+            |error| LoadError::Io{file: path.to_path_buf(), error}
+        )?;
+
+        let is_csv = path.extension()
+            .map(|extension| extension.eq_ignore_ascii_case("csv"))
+            .unwrap_or(false);
+
+        let parsed =
+            if is_csv {
+                PriceFile::parse_csv(&source, database)
+            } else {
+                PriceFile::parse_json(&source, database)
+            };
+
+        let listings = parsed.map_err(
+            |problems| LoadError::Invalid{file: path.to_path_buf(), problems}
+        )?;
+
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        return Ok(PriceFile{listings, modified});
+    }
+
+    fn parse_csv(source: &str, database: &Database)
+    -> Result<HashMap<ItemId, Item>, Vec<Problem>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(source.as_bytes());
+
+        let headers = reader.headers()
+            .map_err(|error| vec![csv_problem(&error)])?
+            .clone();
+
+        let mut listings = HashMap::<ItemId, Item>::new();
+        let mut problems = Vec::<Problem>::new();
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(error) => {
+                    problems.push(csv_problem(&error));
+                    continue;
+                }
+            };
+
+            let line = record.position()
+                .map(|position| position.line() as usize);
+            match record.deserialize::<PriceEntry>(Some(&headers)) {
+                Ok(entry) => match entry.item.resolve(database) {
+                    Some(id) => {
+                        listings.insert(id, entry.into_item(id));
+                    },
+                    None => {
+                        problems.push(
+                            unknown_item(String::new(), line, &entry.item)
+                        );
+                    }
+                },
+                Err(error) => problems.push(csv_problem(&error))
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(listings);
+        }
+
+        return Err(problems);
+    }
+
+    fn parse_json(source: &str, database: &Database)
+    -> Result<HashMap<ItemId, Item>, Vec<Problem>> {
+        let entries: Vec<JsonEntry> = serde_json::from_str(source).map_err(
+            |error| vec![Problem::new(
+                String::new(),
+                Some(error.line()),
+                Some(error.column()),
+                error.to_string()
+            )]
+        )?;
+
+        let mut listings = HashMap::<ItemId, Item>::new();
+        let mut problems = Vec::<Problem>::new();
+        for (index, entry) in entries.into_iter().enumerate() {
+            let reference = match &entry {
+                JsonEntry::Listings(item) => ItemReference::Id(item.id()),
+                JsonEntry::Price(price) => price.item.clone()
+            };
+
+            match reference.resolve(database) {
+                Some(id) => {
+                    let item = match entry {
+                        JsonEntry::Listings(item) => item,
+                        JsonEntry::Price(price) => price.into_item(id)
+                    };
+                    listings.insert(id, item);
+                },
+                None => problems.push(
+                    unknown_item(format!("[{}]", index), None, &reference)
+                )
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(listings);
+        }
+
+        return Err(problems);
+    }
+}
+
+impl MarketSource for PriceFile {
+    fn listings(&mut self, _database: &Database, items: &HashSet<ItemId>)
+    -> Listings {
+        return Listings::new(
+            items.iter()
+                .filter_map(|item| {
+                    Some((*item, self.listings.get(item)?.clone()))
+                })
+                .collect()
+        );
+    }
+
+    fn recorded(&self, _items: &HashSet<ItemId>) -> Option<SystemTime> {
+        return self.modified;
+    }
+}
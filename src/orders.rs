@@ -0,0 +1,77 @@
+use crate::database::ItemId;
+use crate::tp_rest::{Item, Price};
+
+use std::collections::HashMap;
+
+/// How much a buy order is placed above the highest existing buy order, unless
+/// another offset is given.
+pub const DEFAULT_OFFSET: i32 = 1;
+
+/// The unit price of a buy order placed `offset` above the highest existing
+/// buy order of an item. Returns [None] if there are no buy orders to price
+/// it against, or if buying instantly from the sell listings would cost no
+/// more.
+pub fn order_price(item: &Item, offset: i32) -> Option<u32> {
+    let highest = item.buys().first()?.unit_price();
+    let price = std::cmp::max(highest as i64 + offset as i64, 1) as u32;
+
+    if let Some(lowest) = item.sells().first() {
+        if lowest.unit_price() <= price {
+            return None;
+        }
+    }
+
+    return Some(price);
+}
+
+/// How many items existing buy orders already ask for at `price` or more.
+/// Sellers fill those orders first.
+pub fn demand_ahead(item: &Item, price: u32) -> u32 {
+    return item.buys().iter()
+        .take_while(|tier| price <= tier.unit_price())
+        .map(|tier| tier.quantity())
+        .sum();
+}
+
+/// What it costs to buy `quantity` of an item instantly from its sell
+/// listings. Returns [None] if the listings do not hold that many.
+pub fn instant_cost(item: &Item, quantity: u32) -> Option<u32> {
+    let mut cost = 0;
+    let mut needed = quantity;
+    for tier in item.sells() {
+        if needed == 0 {
+            break;
+        }
+
+        let bought = std::cmp::min(needed, tier.quantity());
+        cost += bought * tier.unit_price();
+        needed -= bought;
+    }
+
+    if 0 < needed {
+        return None;
+    }
+
+    return Some(cost);
+}
+
+/// Replace the sell listings of each item with a buy order placed `offset`
+/// above its highest buy order, so that the planner prices materials at what
+/// a patient buyer pays. A buy order can be for any quantity. Items without a
+/// cheaper buy order keep their sell listings.
+pub fn with_buy_orders(listings: &HashMap<ItemId, Item>, offset: i32)
+-> HashMap<ItemId, Item> {
+    return listings.iter()
+        .map(|(id, item)| {
+            let item = match order_price(item, offset) {
+                Some(price) => Item::new(
+                    item.id(),
+                    item.buys().clone(),
+                    vec![Price::new(0, price, u32::MAX)]
+                ),
+                None => item.clone()
+            };
+            (*id, item)
+        })
+        .collect();
+}
@@ -1,39 +1,122 @@
-use crate::descriptions::MaterialDescription;
+use crate::database::{Database, ItemId};
+use crate::descriptions::Recipe;
+use crate::inventory::{Inventory, Valuation, Withdrawal};
+use crate::profit;
 use crate::tp_rest::{Item, Price};
+use crate::targets::{Must, Target};
 
 use std::rc::Rc;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet};
 use std::cmp::Reverse;
-use std::iter::FromIterator;
+use std::mem::size_of;
 use std::ops::Add;
+use std::time::{Duration, Instant};
 
 use priority_queue::PriorityQueue;
 use by_address::ByAddress;
+use im_rc::{HashMap as PersistentMap, Vector};
 
-#[derive(Debug)]
+/// Crafting an item some number of times with one recipe.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Craft {
-    recipe: HashMap<String, u32>
+    item: ItemId,
+    name: String,
+    recipe: Recipe<ItemId>,
+    crafts: u32
 }
 
-#[derive(Clone, Debug)]
+impl Craft {
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    pub fn recipe(&self) -> &Recipe<ItemId> {
+        return &self.recipe;
+    }
+
+    pub fn crafts(&self) -> u32 {
+        return self.crafts;
+    }
+
+    /// How many items the crafts make in total.
+    pub fn produced(&self) -> u32 {
+        return self.crafts * self.recipe.output();
+    }
+}
+
+/// A quantity of an item left over from crafting whole batches.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Surplus {
+    item: ItemId,
+    name: String,
+    quantity: u32,
+    value: u32
+}
+
+impl Surplus {
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    pub fn quantity(&self) -> u32 {
+        return self.quantity;
+    }
+
+    /// What the items would sell for to the current buy orders, after fees.
+    /// This is only filled in for the `leftovers` of a [Plan].
+    pub fn value(&self) -> u32 {
+        return self.value;
+    }
+}
+
+/// A purchase from the Trading Post.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Buy {
+    item: ItemId,
     name: String,
     quantity: u32,
     cost: u32
 }
 
-impl<'a, 'b> Add<&'b Buy> for &'a Buy {
+impl Buy {
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    pub fn quantity(&self) -> u32 {
+        return self.quantity;
+    }
+
+    pub fn cost(&self) -> u32 {
+        return self.cost;
+    }
+}
+
+impl<'b> Add<&'b Buy> for &Buy {
     type Output = Buy;
 
     fn add(self, other: &'b Buy) -> Buy {
-        if self.name != other.name {
+        if self.item != other.item {
             panic!(
-                "Name mismatch while adding buys: [{}] vs [{}]",
+                "Item mismatch while adding buys: [{}] vs [{}]",
                 self.name, other.name
             );
         }
 
         Buy{
+            item: self.item,
             name: self.name.clone(),
             quantity: self.quantity + other.quantity,
             cost: self.cost + other.cost
@@ -41,25 +124,46 @@ impl<'a, 'b> Add<&'b Buy> for &'a Buy {
     }
 }
 
-#[derive(Clone, Debug)]
+/// A purchase from a vendor.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Vendor {
+    item: ItemId,
     name: String,
     quantity: u32,
     cost: u32
 }
 
-impl<'a, 'b> Add<&'b Vendor> for &'a Vendor {
+impl Vendor {
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    pub fn quantity(&self) -> u32 {
+        return self.quantity;
+    }
+
+    pub fn cost(&self) -> u32 {
+        return self.cost;
+    }
+}
+
+impl<'b> Add<&'b Vendor> for &Vendor {
     type Output = Vendor;
 
     fn add(self, other: &'b Vendor) -> Vendor {
-        if self.name != other.name {
+        if self.item != other.item {
             panic!(
-                "Name mismatch while adding vendors: [{}] vs [{}]",
+                "Item mismatch while adding vendors: [{}] vs [{}]",
                 self.name, other.name
             );
         }
 
         Vendor{
+            item: self.item,
             name: self.name.clone(),
             quantity: self.quantity + other.quantity,
             cost: self.cost + other.cost
@@ -67,331 +171,1620 @@ impl<'a, 'b> Add<&'b Vendor> for &'a Vendor {
     }
 }
 
+/// Items that the account already owns, used instead of acquiring them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Owned {
+    item: ItemId,
+    name: String,
+    quantity: u32,
+    cost: u32,
+    from: Vec<Withdrawal>
+}
+
+impl Owned {
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    pub fn quantity(&self) -> u32 {
+        return self.quantity;
+    }
+
+    /// What using the items gives up, according to the [Valuation] that the
+    /// plan was made with.
+    pub fn cost(&self) -> u32 {
+        return self.cost;
+    }
+
+    /// Where to take the items from. This is only filled in for the owned
+    /// items of a whole [Plan].
+    pub fn from(&self) -> &Vec<Withdrawal> {
+        return &self.from;
+    }
+}
+
 #[derive(Debug)]
-pub enum Choice {
+enum Choice {
     Craft(Craft),
     Buy(Buy),
-    Vendor(Vendor)
+    Vendor(Vendor),
+    Surplus(Surplus),
+    Owned(Owned)
 }
 
 #[derive(Debug)]
 struct Parent {
     node: Rc<SearchNode>,
-    choice: Choice
+    choice: Choice,
+    target: usize,
+    origin: Option<usize>
 }
 
-#[derive(Clone,Debug)]
+// What is still needed of an item for one use of it: the target it is for,
+// and the craft that it is an ingredient of, unless it is the target itself.
+// Uses of the same item are kept apart, so that each of them can be acquired
+// in its own way. The closed set of the search keeps that from expanding the
+// same state once for every order of them.
+#[derive(Clone, Debug)]
 struct Remainder {
-    name: String,
-    quantity: u32
+    item: ItemId,
+    quantity: u32,
+    target: usize,
+    origin: Option<usize>,
+    must: Option<Must>
+}
+
+impl Remainder {
+    // Whatever is still needed after [quantity] of the items.
+    fn rest(&self, quantity: u32) -> Option<Remainder> {
+        if quantity < self.quantity {
+            return Some(Remainder{
+                quantity: self.quantity - quantity,
+                ..self.clone()
+            });
+        }
+
+        return None;
+    }
+
+    fn may_buy(&self) -> bool {
+        return self.must != Some(Must::Craft);
+    }
+
+    fn may_craft(&self) -> bool {
+        return self.must != Some(Must::Buy);
+    }
+
+    // Targets that say how they must be acquired are never taken from what
+    // the account owns.
+    fn may_use_owned(&self) -> bool {
+        return self.must.is_none();
+    }
+}
+
+// The sell listings of one item as a search node sees them. The tiers are
+// shared by every node, which only keep track of how far into them they have
+// bought.
+#[derive(Clone, Debug)]
+struct OrderBook {
+    tiers: Rc<Vec<Price>>,
+    next: usize,
+    bought: u32
 }
 
+impl OrderBook {
+    fn new(tiers: Vec<Price>) -> OrderBook {
+        return OrderBook{tiers: Rc::new(tiers), next: 0, bought: 0};
+    }
+
+    // The cheapest tier that is left, without what was already bought from it.
+    fn front(&self) -> Option<Price> {
+        let tier = self.tiers.get(self.next)?;
+        if self.bought == 0 {
+            return Some(tier.clone());
+        }
+
+        return Some(tier.copy_reduced_by(self.bought));
+    }
+
+    // The book after buying [quantity] items from the cheapest tier, which
+    // must hold at least that many.
+    fn after_buying(&self, quantity: u32) -> OrderBook {
+        let mut book = self.clone();
+        book.bought += quantity;
+        if book.bought == self.tiers[self.next].quantity() {
+            book.next += 1;
+            book.bought = 0;
+        }
+
+        return book;
+    }
+
+    // How many items all the tiers held before anything was bought.
+    fn depth(&self) -> u32 {
+        return self.tiers.iter().map(|tier| tier.quantity()).sum();
+    }
+
+    // How many items have been bought from the book.
+    fn taken(&self) -> u32 {
+        let emptied: u32 = self.tiers[..self.next].iter()
+            .map(|tier| tier.quantity())
+            .sum();
+        return emptied + self.bought;
+    }
+}
+
+// The state of a search node is shared with its parent wherever it has not
+// changed, so expanding a node copies only what a choice actually changes.
 #[derive(Debug)]
 struct SearchNode {
     parent: Option<Parent>,
-    remaining: Vec<Remainder>,
-    listings: HashMap<String, VecDeque<Price>>,
-    descriptions: Rc<HashMap<String, MaterialDescription>>,
+    remaining: Vector<Remainder>,
+    listings: PersistentMap<ItemId, OrderBook>,
+    surplus: PersistentMap<ItemId, u32>,
+    owned: PersistentMap<ItemId, u32>,
+    crafts: usize,
+    depth: usize,
     total_cost: u32
 }
 
+// Everything about a search node that decides which plans can still follow
+// from it. What the earlier choices were made for does not matter, so nodes
+// that made the same choices in another order have the same state.
+#[derive(PartialEq, Eq, Hash)]
+struct SearchState {
+    remaining: Vec<(ItemId, Option<Must>, u32)>,
+    listings: Vec<(ItemId, usize, u32)>,
+    surplus: Vec<(ItemId, u32)>,
+    owned: Vec<(ItemId, u32)>
+}
+
+impl SearchState {
+    // A rough estimate of how much memory the state takes up.
+    fn footprint(&self) -> usize {
+        return size_of::<SearchState>()
+            + self.remaining.len() * size_of::<(ItemId, Option<Must>, u32)>()
+            + self.listings.len() * size_of::<(ItemId, usize, u32)>()
+            + (self.surplus.len() + self.owned.len())
+                * size_of::<(ItemId, u32)>();
+    }
+}
+
 type SearchNodePtr = ByAddress<Rc<SearchNode>>;
-type SearchQueue = PriorityQueue<SearchNodePtr, Reverse<u32>>;
+type OwnedValues = HashMap<ItemId, u32>;
+
+// The least that one unit of each item could cost in any plan. An item costs
+// at least the cheapest of its sell listings, its vendor price, the value of
+// the owned items and one batch of its cheapest recipe divided by the output
+// of the recipe, rounded down. Buying from the order book only gets more
+// expensive, and items left over from a batch are paid for by the batch, so
+// no plan can get the items for less.
+struct LowerBounds {
+    unit_costs: HashMap<ItemId, u32>
+}
+
+impl LowerBounds {
+    fn new(
+        database: &Database,
+        listings: &PersistentMap<ItemId, OrderBook>,
+        owned_values: &OwnedValues
+    ) -> LowerBounds {
+        let mut unit_costs = HashMap::<ItemId, u32>::new();
+        for material in database.materials().values() {
+            let item = material.id();
+            let cheapest = listings.get(&item).into_iter()
+                .flat_map(|book| book.tiers.iter().map(|p| p.unit_price()))
+                .chain(*material.vendor())
+                .chain(owned_values.get(&item).copied())
+                .min();
+
+            if let Some(cheapest) = cheapest {
+                unit_costs.insert(item, cheapest);
+            }
+        }
+
+        // Recipes can use items that are themselves crafted, so the bounds
+        // are lowered by their recipes until none of them changes. They only
+        // ever go down, so this stops even when the recipes form a cycle.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for material in database.materials().values() {
+                for recipe in material.recipes() {
+                    let batch: Option<u64> = recipe.ingredients().iter()
+                        .map(|(item, quantity)| {
+                            unit_costs.get(item)
+                                .map(|cost| *cost as u64 * *quantity as u64)
+                        })
+                        .sum();
+
+                    let unit_cost = match batch {
+                        Some(batch) => (batch / recipe.output() as u64)
+                            .min(u32::MAX as u64) as u32,
+                        None => continue
+                    };
+
+                    let current = unit_costs.entry(material.id())
+                        .or_insert(u32::MAX);
+                    if unit_cost < *current {
+                        *current = unit_cost;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        return LowerBounds{unit_costs};
+    }
+
+    // The least that acquiring everything that remains for a node could cost.
+    // Items left over from earlier crafts are free, and they can go into any
+    // craft that needs them, not only replace the same item. So the bound of
+    // every item held as surplus comes off the bound of what remains, however
+    // that surplus ends up being used. Items that cannot be acquired at all
+    // count for nothing, since the search finds out about those when it tries
+    // to expand them.
+    fn estimate(&self, node: &SearchNode) -> u32 {
+        let unit_cost =
+            |item: &ItemId| self.unit_costs.get(item).copied().unwrap_or(0);
+
+        let needed: u64 = node.remaining.iter()
+            .map(|r| r.quantity as u64 * unit_cost(&r.item) as u64)
+            .sum();
+
+        let held: u64 = node.surplus.iter()
+            .map(|(item, quantity)| *quantity as u64 * unit_cost(item) as u64)
+            .sum();
+
+        let estimate = needed.saturating_sub(held);
+        return estimate.min(u32::MAX as u64) as u32;
+    }
+}
+
+// Nodes are searched in order of their cost plus the least that the rest of
+// their plan could cost. Since that estimate is never too high, the first
+// complete plan to come out of the queue is still the cheapest. Between nodes
+// that tie, the one with more choices made comes first, since it is closer to
+// a complete plan. Otherwise equally cheap ways to get the same items, such as
+// using an owned item or buying it for what it is worth, are all tried in
+// turn.
+struct SearchQueue<'a> {
+    nodes: PriorityQueue<SearchNodePtr, (Reverse<u32>, usize)>,
+    bounds: &'a LowerBounds,
+    footprint: usize
+}
+
+impl<'a> SearchQueue<'a> {
+    fn new(bounds: &'a LowerBounds) -> SearchQueue<'a> {
+        return SearchQueue{nodes: PriorityQueue::new(), bounds, footprint: 0};
+    }
+
+    fn push(&mut self, node: impl Into<Rc<SearchNode>>) {
+        let node = node.into();
+        let estimate =
+            node.total_cost.saturating_add(self.bounds.estimate(&node));
+        let depth = node.depth;
+        self.footprint += node.footprint();
+        self.nodes.push(ByAddress(node), (Reverse(estimate), depth));
+    }
+
+    // The least that any complete plan through a node in the queue could
+    // cost.
+    fn lowest_estimate(&self) -> Option<u32> {
+        return self.nodes.peek().map(|(_, (Reverse(estimate), _))| *estimate);
+    }
+
+    // Take the most promising node out of the queue, along with the least
+    // that any complete plan through it could cost.
+    fn pop(&mut self) -> Option<(Rc<SearchNode>, u32)> {
+        return self.nodes.pop()
+            .map(|(node, (Reverse(estimate), _))| (node.0, estimate));
+    }
+
+    fn append(&mut self, other: &mut SearchQueue) {
+        self.nodes.append(&mut other.nodes);
+        self.footprint += other.footprint;
+        other.footprint = 0;
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.nodes.is_empty();
+    }
+}
+
+// What came of expanding a search node.
+enum Expansion {
+    // The node needs nothing more, so it is a complete plan.
+    Complete,
+    Expanded,
+    // There is no way at all to get the next item that the node needs.
+    DeadEnd(Infeasible)
+}
 
 impl SearchNode {
+    fn state(&self) -> SearchState {
+        let remaining = self.remaining.iter()
+            .map(|r| (r.item, r.must, r.quantity))
+            .collect();
+
+        // Books that nothing was bought from are the same for every node.
+        let mut listings: Vec<(ItemId, usize, u32)> = self.listings.iter()
+            .filter(|(_, book)| 0 < book.next || 0 < book.bought)
+            .map(|(item, book)| (*item, book.next, book.bought))
+            .collect();
+        listings.sort_unstable();
+
+        let mut surplus: Vec<(ItemId, u32)> = self.surplus.iter()
+            .map(|(item, quantity)| (*item, *quantity))
+            .collect();
+        surplus.sort_unstable();
+
+        let mut owned: Vec<(ItemId, u32)> = self.owned.iter()
+            .map(|(item, quantity)| (*item, *quantity))
+            .collect();
+        owned.sort_unstable();
+
+        return SearchState{remaining, listings, surplus, owned};
+    }
+
+    // A rough estimate of how much memory the node takes up on its own. Most
+    // of its state is shared with its parent, except for the remaining items
+    // that it changed.
+    fn footprint(&self) -> usize {
+        return size_of::<SearchNode>() + size_of::<Parent>()
+            + self.remaining.len() * size_of::<Remainder>();
+    }
+
     fn expand(
         parent: &Rc<Self>,
-        queue: &mut SearchQueue
-    ) -> bool {
+        queue: &mut SearchQueue,
+        database: &Database,
+        owned_values: &OwnedValues
+    ) -> Expansion {
         if parent.as_ref().remaining.is_empty() {
-            return false;
+            return Expansion::Complete;
         }
 
-        let mut expansions = SearchQueue::new();
-        SearchNode::expand_buy(parent, &mut expansions);
-        SearchNode::expand_vendor(parent, &mut expansions);
-        SearchNode::expand_recipes(parent, &mut expansions);
+        // Items left over from earlier crafts are free, so they are always
+        // used up before considering any other way to get the next item.
+        if SearchNode::expand_surplus(parent, queue, database) {
+            return Expansion::Expanded;
+        }
 
-        if expansions.is_empty() {
-            panic!(
-                "Failed to expand {}",
-                parent.as_ref().remaining.get(0).unwrap().name
+        // The same goes for items that the account owns if they are free.
+        // Otherwise using them is one more choice.
+        let next = parent.as_ref().remaining.last().unwrap().item;
+        let owned_value = owned_values.get(&next).copied().unwrap_or(0);
+        if owned_value == 0
+        && SearchNode::expand_owned(parent, queue, database, 0) {
+            return Expansion::Expanded;
+        }
+
+        let mut expansions = SearchQueue::new(queue.bounds);
+        if 0 < owned_value {
+            SearchNode::expand_owned(
+                parent, &mut expansions, database, owned_value
             );
         }
+        SearchNode::expand_buy(parent, &mut expansions, database);
+        SearchNode::expand_vendor(parent, &mut expansions, database);
+        SearchNode::expand_recipes(parent, &mut expansions, database);
+
+        if expansions.is_empty() {
+            return Expansion::DeadEnd(parent.blocked_by(database, false));
+        }
 
         queue.append(&mut expansions);
-        return true;
+        return Expansion::Expanded;
     }
 
-    fn expand_buy(
+    // Describe why the next item that the node needs cannot be acquired.
+    // [cycle] says that the node is back in a state that was already
+    // expanded, so the item can only be crafted from itself.
+    fn blocked_by(&self, database: &Database, cycle: bool) -> Infeasible {
+        let next = self.remaining.last().unwrap();
+        let book = self.listings.get(&next.item);
+        let taken = book.map(|book| book.taken()).unwrap_or(0);
+        return Infeasible{
+            item: next.item,
+            name: database.name(next.item),
+            needed: taken + next.quantity,
+            listed: book.map(|book| book.depth()).unwrap_or(0),
+            must: next.must,
+            cycle,
+            described: database.get(next.item).is_some()
+        };
+    }
+
+    fn expand_surplus(
         parent: &Rc<Self>,
-        queue: &mut SearchQueue
-    ) {
+        queue: &mut SearchQueue,
+        database: &Database
+    ) -> bool {
         let p = parent.as_ref();
         let mut remaining = p.remaining.clone();
 
-        if let Some(next) = remaining.pop() {
-            if let Some(_) = p.listings.get(&next.name) {
-                let mut new_listings = p.listings.clone();
-                let prices: &mut VecDeque<Price> =
-                    new_listings.get_mut(&next.name).unwrap();
-
-                let mut quantity = next.quantity;
-                if let Some(next_price) = prices.pop_front() {
-                    if next.quantity < next_price.quantity() {
-                        prices.push_front(
-                            next_price.copy_reduced_by(next.quantity)
-                        );
-                    } else if next_price.quantity() < next.quantity {
-                        quantity = next_price.quantity();
-                        remaining.push(
-                            Remainder{
-                                name: next.name.clone(),
-                                quantity: next.quantity - next_price.quantity()
+        if let Some(next) = remaining.pop_back() {
+            if !next.may_craft() {
+                return false;
+            }
+
+            if let Some(available) = p.surplus.get(&next.item) {
+                let mut new_surplus = p.surplus.clone();
+                let quantity = std::cmp::min(*available, next.quantity);
+                if quantity < *available {
+                    new_surplus.insert(next.item, available - quantity);
+                } else {
+                    new_surplus.remove(&next.item);
+                }
+
+                if let Some(rest) = next.rest(quantity) {
+                    remaining.push_back(rest);
+                }
+
+                queue.push(
+                    SearchNode{
+                        parent: Some(
+                            Parent{
+                                node: parent.clone(),
+                                choice: Choice::Surplus(Surplus{
+                                    item: next.item,
+                                    name: database.name(next.item),
+                                    quantity,
+                                    value: 0
+                                }),
+                                target: next.target,
+                                origin: next.origin
                             }
-                        )
+                        ),
+                        remaining,
+                        listings: p.listings.clone(),
+                        surplus: new_surplus,
+                        owned: p.owned.clone(),
+                        crafts: p.crafts,
+                        depth: p.depth + 1,
+                        total_cost: p.total_cost
                     }
+                );
 
-                    let added_cost = quantity * next_price.unit_price();
-                    let new_cost = p.total_cost + added_cost;
-                    queue.push(
-                        ByAddress(Rc::new(
-                            SearchNode{
-                                parent: Some(
-                                    Parent{
-                                        node: parent.clone(),
-                                        choice: Choice::Buy(Buy{
-                                            name: next.name.clone(),
-                                            quantity: quantity,
-                                            cost: added_cost
-                                        }),
-                                    }
-                                ),
-                                remaining: remaining,
-                                listings: new_listings,
-                                descriptions: p.descriptions.clone(),
-                                total_cost: new_cost
-                            }
-                        )),
-                        Reverse(new_cost)
-                    );
-                }
+                return true;
             }
         }
+
+        return false;
+    }
+
+    fn expand_owned(
+        parent: &Rc<Self>,
+        queue: &mut SearchQueue,
+        database: &Database,
+        unit_value: u32
+    ) -> bool {
+        let p = parent.as_ref();
+        let mut remaining = p.remaining.clone();
+
+        let next = match remaining.pop_back() {
+            Some(next) => next,
+            None => return false
+        };
+
+        if !next.may_use_owned() {
+            return false;
+        }
+
+        let available = match p.owned.get(&next.item) {
+            Some(available) => *available,
+            None => return false
+        };
+
+        let mut new_owned = p.owned.clone();
+        let quantity = std::cmp::min(available, next.quantity);
+        if quantity < available {
+            new_owned.insert(next.item, available - quantity);
+        } else {
+            new_owned.remove(&next.item);
+        }
+
+        if let Some(rest) = next.rest(quantity) {
+            remaining.push_back(rest);
+        }
+
+        let added_cost = quantity * unit_value;
+        let new_cost = p.total_cost + added_cost;
+        queue.push(
+            SearchNode{
+                parent: Some(
+                    Parent{
+                        node: parent.clone(),
+                        choice: Choice::Owned(Owned{
+                            item: next.item,
+                            name: database.name(next.item),
+                            quantity,
+                            cost: added_cost,
+                            from: Vec::new()
+                        }),
+                        target: next.target,
+                        origin: next.origin
+                    }
+                ),
+                remaining,
+                listings: p.listings.clone(),
+                surplus: p.surplus.clone(),
+                owned: new_owned,
+                crafts: p.crafts,
+                depth: p.depth + 1,
+                total_cost: new_cost
+            }
+        );
+
+        return true;
+    }
+
+    fn expand_buy(
+        parent: &Rc<Self>,
+        queue: &mut SearchQueue,
+        database: &Database
+    ) {
+        let p = parent.as_ref();
+        let mut remaining = p.remaining.clone();
+
+        let next = match remaining.pop_back() {
+            Some(next) => next,
+            None => return
+        };
+
+        if !next.may_buy() {
+            return;
+        }
+
+        let book = match p.listings.get(&next.item) {
+            Some(book) => book,
+            None => return
+        };
+
+        let next_price = match book.front() {
+            Some(next_price) => next_price,
+            None => return
+        };
+
+        let quantity = std::cmp::min(next.quantity, next_price.quantity());
+        if let Some(rest) = next.rest(quantity) {
+            remaining.push_back(rest);
+        }
+
+        let added_cost = quantity * next_price.unit_price();
+        let new_cost = p.total_cost + added_cost;
+        queue.push(
+            SearchNode{
+                parent: Some(
+                    Parent{
+                        node: parent.clone(),
+                        choice: Choice::Buy(Buy{
+                            item: next.item,
+                            name: database.name(next.item),
+                            quantity,
+                            cost: added_cost
+                        }),
+                        target: next.target,
+                        origin: next.origin
+                    }
+                ),
+                remaining,
+                listings: p.listings.update(
+                    next.item, book.after_buying(quantity)
+                ),
+                surplus: p.surplus.clone(),
+                owned: p.owned.clone(),
+                crafts: p.crafts,
+                depth: p.depth + 1,
+                total_cost: new_cost
+            }
+        );
     }
 
     fn expand_vendor(
         parent: &Rc<Self>,
-        queue: &mut SearchQueue
+        queue: &mut SearchQueue,
+        database: &Database
     ) {
         let p = parent.as_ref();
         let mut remaining = p.remaining.clone();
 
-        if let Some(next) = remaining.pop() {
-            if let Some(description) = p.descriptions.as_ref().get(&next.name) {
-                if let Some(vendor_price) = description.vendor() {
+        if let Some(next) = remaining.pop_back() {
+            if !next.may_buy() {
+                return;
+            }
+
+            // Items without a description can only be bought, and the node
+            // is blocked by them if they cannot be.
+            if let Some(material) = database.get(next.item) {
+                if let Some(vendor_price) = material.vendor() {
                     let added_cost = next.quantity * vendor_price;
                     let new_cost = p.total_cost + added_cost;
                     queue.push(
-                        ByAddress(Rc::new(
-                            SearchNode{
-                                parent: Some(
-                                    Parent{
-                                        node: parent.clone(),
-                                        choice: Choice::Vendor(Vendor{
-                                            name: next.name.clone(),
-                                            quantity: next.quantity,
-                                            cost: added_cost
-                                        }),
-                                    }
-                                ),
-                                remaining: remaining,
-                                listings: p.listings.clone(),
-                                descriptions: p.descriptions.clone(),
-                                total_cost: new_cost
-                            }
-                        )),
-                        Reverse(new_cost)
+                        SearchNode{
+                            parent: Some(
+                                Parent{
+                                    node: parent.clone(),
+                                    choice: Choice::Vendor(Vendor{
+                                        item: next.item,
+                                        name: database.name(next.item),
+                                        quantity: next.quantity,
+                                        cost: added_cost
+                                    }),
+                                    target: next.target,
+                                    origin: next.origin
+                                }
+                            ),
+                            remaining,
+                            listings: p.listings.clone(),
+                            surplus: p.surplus.clone(),
+                            owned: p.owned.clone(),
+                            crafts: p.crafts,
+                            depth: p.depth + 1,
+                            total_cost: new_cost
+                        }
                     );
                 }
-            } else {
-                panic!(
-                    "[SearchNode::expand_vendor] Missing description for [{}]",
-                    next.name
-                );
             }
         }
     }
 
     fn expand_recipes(
         parent: &Rc<Self>,
-        queue: &mut SearchQueue
+        queue: &mut SearchQueue,
+        database: &Database
     ) {
         let p = parent.as_ref();
         let mut remaining = p.remaining.clone();
 
-        if let Some(next) = remaining.pop() {
-            if let Some(description) = p.descriptions.as_ref().get(&next.name) {
-                for recipe in description.recipes() {
+        if let Some(next) = remaining.pop_back() {
+            if !next.may_craft() {
+                return;
+            }
+
+            if let Some(material) = database.get(next.item) {
+                for recipe in material.recipes() {
+                    // Crafts always produce whole batches, so anything beyond
+                    // the requested quantity is kept as surplus.
+                    let crafts = next.quantity.div_ceil(recipe.output());
+                    let produced = crafts * recipe.output();
+
+                    // The ingredients are added in order of their IDs, so
+                    // that the search does not depend on the order of a hash
+                    // map.
+                    let mut ingredients: Vec<(&ItemId, &u32)> =
+                        recipe.ingredients().iter().collect();
+                    ingredients.sort_unstable();
+
                     let mut remaining_with_recipe = remaining.clone();
-                    for (name, quantity) in recipe {
-                        remaining_with_recipe.push(
+                    for (item, quantity) in ingredients {
+                        remaining_with_recipe.push_back(
                             Remainder{
-                                name: name.clone(),
-                                quantity: quantity.clone() * next.quantity
+                                item: *item,
+                                quantity: quantity * crafts,
+                                target: next.target,
+                                origin: Some(p.crafts),
+                                must: None
                             }
                         );
                     }
 
+                    let mut surplus_with_recipe = p.surplus.clone();
+                    if next.quantity < produced {
+                        *surplus_with_recipe.entry(next.item)
+                            .or_insert(0) += produced - next.quantity;
+                    }
+
                     queue.push(
-                        ByAddress(Rc::new(
-                            SearchNode{
-                                parent: Some(
-                                    Parent{
-                                        node: parent.clone(),
-                                        choice: Choice::Craft(
-                                            Craft{
-                                                recipe: recipe.clone()
-                                            }
-                                        )
-                                    }
-                                ),
-                                remaining: remaining_with_recipe,
-                                listings: p.listings.clone(),
-                                descriptions: p.descriptions.clone(),
-                                total_cost: p.total_cost
-                            }
-                        )),
-                        Reverse(p.total_cost)
+                        SearchNode{
+                            parent: Some(
+                                Parent{
+                                    node: parent.clone(),
+                                    choice: Choice::Craft(
+                                        Craft{
+                                            item: next.item,
+                                            name: database.name(next.item),
+                                            recipe: recipe.clone(),
+                                            crafts
+                                        }
+                                    ),
+                                    target: next.target,
+                                    origin: next.origin
+                                }
+                            ),
+                            remaining: remaining_with_recipe,
+                            listings: p.listings.clone(),
+                            surplus: surplus_with_recipe,
+                            owned: p.owned.clone(),
+                            crafts: p.crafts + 1,
+                            depth: p.depth + 1,
+                            total_cost: p.total_cost
+                        }
                     );
                 }
-            } else {
-                panic!(
-                    "[SearchNode::expand_recipes] Missing description for [{}]",
-                    next.name
-                );
             }
         }
     }
 }
 
-#[derive(Debug)]
-struct Result {
-    buy: HashMap<String, Buy>,
-    vendor: HashMap<String, Vendor>
+/// A craft in the crafting tree, along with how each of its ingredients is
+/// acquired.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CraftStep {
+    craft: Craft,
+    inputs: Vec<Step>
 }
 
-fn flatten(solution: &Rc<SearchNode>) -> Result {
-    let mut result = Result{
-        buy: HashMap::<String, Buy>::new(),
-        vendor: HashMap::<String, Vendor>::new()
-    };
+impl CraftStep {
+    pub fn craft(&self) -> &Craft {
+        return &self.craft;
+    }
+
+    pub fn inputs(&self) -> &Vec<Step> {
+        return &self.inputs;
+    }
+}
+
+/// One node of the crafting tree.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Step {
+    Craft(CraftStep),
+    Buy(Buy),
+    Vendor(Vendor),
+    Surplus(Surplus),
+    Owned(Owned)
+}
+
+// Purchases of the same item for the same purpose are merged into one step,
+// since the search splits them up by order book tier.
+fn push_step(steps: &mut Vec<Step>, step: Step) {
+    for existing in steps.iter_mut() {
+        match (existing, &step) {
+            (Step::Buy(a), Step::Buy(b)) if a.item == b.item => {
+                *a = &*a + b;
+                return;
+            },
+            (Step::Vendor(a), Step::Vendor(b)) if a.item == b.item => {
+                *a = &*a + b;
+                return;
+            },
+            (Step::Surplus(a), Step::Surplus(b)) if a.item == b.item => {
+                a.quantity += b.quantity;
+                return;
+            },
+            (Step::Owned(a), Step::Owned(b)) if a.item == b.item => {
+                a.quantity += b.quantity;
+                a.cost += b.cost;
+                return;
+            },
+            _ => { }
+        }
+    }
+
+    steps.push(step);
+}
+
+/// The part of a plan that acquires one target.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TargetPlan {
+    target: Target,
+    cost: u32,
+    steps: Vec<Step>
+}
+
+impl TargetPlan {
+    pub fn target(&self) -> &Target {
+        return &self.target;
+    }
+
+    /// The cost of the purchases made for this target, along with the value of
+    /// the owned items used for it. Items left over from crafting for another
+    /// target are free here.
+    pub fn cost(&self) -> u32 {
+        return self.cost;
+    }
+
+    pub fn steps(&self) -> &Vec<Step> {
+        return &self.steps;
+    }
+}
+
+/// The cheapest way found to acquire a list of targets.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Plan {
+    total_cost: u32,
+    opportunity_cost: u32,
+    leftover_value: u32,
+    targets: Vec<TargetPlan>,
+    buys: Vec<Buy>,
+    vendors: Vec<Vendor>,
+    crafts: Vec<Craft>,
+    owned: Vec<Owned>,
+    leftovers: Vec<Surplus>,
+    optimality_gap: u32
+}
+
+impl Plan {
+    /// The cost of every purchase, along with the `opportunity_cost`.
+    pub fn total_cost(&self) -> u32 {
+        return self.total_cost;
+    }
+
+    /// The value of the owned items that the plan uses.
+    pub fn opportunity_cost(&self) -> u32 {
+        return self.opportunity_cost;
+    }
+
+    pub fn targets(&self) -> &Vec<TargetPlan> {
+        return &self.targets;
+    }
+
+    pub fn buys(&self) -> &Vec<Buy> {
+        return &self.buys;
+    }
+
+    pub fn vendors(&self) -> &Vec<Vendor> {
+        return &self.vendors;
+    }
+
+    /// The crafts of the plan, merged by item and recipe, in an order where
+    /// every craft comes after the crafts of its ingredients.
+    pub fn crafts(&self) -> &Vec<Craft> {
+        return &self.crafts;
+    }
+
+    /// Items that the account already owns and the plan uses, along with
+    /// where to take them from.
+    pub fn owned(&self) -> &Vec<Owned> {
+        return &self.owned;
+    }
+
+    /// Items that the crafts produce beyond what the plan needs, along with
+    /// what they would sell for.
+    pub fn leftovers(&self) -> &Vec<Surplus> {
+        return &self.leftovers;
+    }
+
+    /// What all the `leftovers` would sell for to the current buy orders,
+    /// after fees.
+    pub fn leftover_value(&self) -> u32 {
+        return self.leftover_value;
+    }
+
+    /// How much less than this plan the cheapest plan could cost. This is
+    /// zero unless the search ran out of its [Options] budget before it could
+    /// be sure that it found the cheapest plan.
+    pub fn optimality_gap(&self) -> u32 {
+        return self.optimality_gap;
+    }
+}
+
+/// Why no plan could be found for a list of targets: one of the items that
+/// the most promising plan needs cannot be acquired at all.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Infeasible {
+    item: ItemId,
+    name: String,
+    needed: u32,
+    listed: u32,
+    must: Option<Must>,
+    cycle: bool,
+    described: bool
+}
+
+impl Infeasible {
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    /// How many of the item the plan needed from the Trading Post.
+    pub fn needed(&self) -> u32 {
+        return self.needed;
+    }
+
+    /// How many of the item the sell listings held in total.
+    pub fn listed(&self) -> u32 {
+        return self.listed;
+    }
+
+    /// How the item had to be acquired, if it is a target that says so.
+    pub fn must(&self) -> Option<Must> {
+        return self.must;
+    }
+
+    /// Whether every way to craft the item leads back to needing the item
+    /// itself, through a recipe cycle.
+    pub fn cycle(&self) -> bool {
+        return self.cycle;
+    }
+
+    /// Whether the item is in the material descriptions at all.
+    pub fn described(&self) -> bool {
+        return self.described;
+    }
+}
+
+impl std::fmt::Display for Infeasible {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.must {
+            _ if self.cycle => write!(
+                f,
+                "[{}] can only be crafted from itself through a recipe cycle, \
+                and {} are needed",
+                self.name, self.needed
+            ),
+            _ if !self.described && self.listed == 0 => write!(
+                f,
+                "[{}] is not in the material descriptions, and {} are needed",
+                self.name, self.needed
+            ),
+            Some(Must::Craft) => write!(
+                f, "[{}] must be crafted, but it has no recipe", self.name
+            ),
+            Some(Must::Buy) if self.listed == 0 => write!(
+                f, "[{}] must be bought, but it is not for sale", self.name
+            ),
+            _ if self.listed == 0 => write!(
+                f,
+                "[{}] cannot be bought or crafted, and {} are needed",
+                self.name, self.needed
+            ),
+            _ => write!(
+                f,
+                "{} x [{}] are needed, but the Trading Post only lists {}",
+                self.needed, self.name, self.listed
+            )
+        }
+    }
+}
+
+impl std::error::Error for Infeasible { }
+
+/// Settings that change what the planner considers the cheapest plan.
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    valuation: Valuation,
+    credit_leftovers: bool,
+    node_limit: Option<usize>,
+    time_limit: Option<Duration>,
+    memory_limit: Option<usize>
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        return Options{
+            valuation: Valuation::SellValue,
+            credit_leftovers: false,
+            node_limit: None,
+            time_limit: None,
+            memory_limit: None
+        };
+    }
+}
+
+impl Options {
+    /// Charge owned items at what the `valuation` says they are worth.
+    pub fn with_valuation(mut self, valuation: Valuation) -> Options {
+        self.valuation = valuation;
+        return self;
+    }
+
+    /// Whether what the leftovers of a plan would sell for counts against its
+    /// cost, so a recipe that leaves valuable leftovers can win over one that
+    /// costs a little less.
+    pub fn with_leftover_credit(mut self, credit_leftovers: bool) -> Options {
+        self.credit_leftovers = credit_leftovers;
+        return self;
+    }
+
+    /// Stop searching after expanding this many search nodes.
+    pub fn with_node_limit(mut self, nodes: usize) -> Options {
+        self.node_limit = Some(nodes);
+        return self;
+    }
+
+    /// Stop searching once this much time has passed.
+    pub fn with_time_limit(mut self, time: Duration) -> Options {
+        self.time_limit = Some(time);
+        return self;
+    }
+
+    /// Stop searching once the search takes up roughly this many bytes.
+    pub fn with_memory_limit(mut self, bytes: usize) -> Options {
+        self.memory_limit = Some(bytes);
+        return self;
+    }
+
+    pub fn valuation(&self) -> Valuation {
+        return self.valuation;
+    }
+
+    pub fn credit_leftovers(&self) -> bool {
+        return self.credit_leftovers;
+    }
+
+    pub fn node_limit(&self) -> Option<usize> {
+        return self.node_limit;
+    }
+
+    pub fn time_limit(&self) -> Option<Duration> {
+        return self.time_limit;
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        return self.memory_limit;
+    }
+
+    fn out_of_budget(
+        &self,
+        nodes: usize,
+        started: Instant,
+        memory: usize
+    ) -> bool {
+        return self.node_limit.is_some_and(|limit| limit <= nodes)
+            || self.time_limit.is_some_and(|limit| limit <= started.elapsed())
+            || self.memory_limit.is_some_and(|limit| limit <= memory);
+    }
+
+    // The greedy completion that runs when the budget is used up may go over
+    // it by a quarter, so that it has some room left to finish a plan in.
+    fn out_of_allowance(
+        &self,
+        nodes: usize,
+        started: Instant,
+        memory: usize
+    ) -> bool {
+        return self.node_limit
+                .is_some_and(|limit| limit + limit / 4 <= nodes)
+            || self.time_limit
+                .is_some_and(|limit| limit + limit / 4 <= started.elapsed())
+            || self.memory_limit
+                .is_some_and(|limit| limit + limit / 4 <= memory);
+    }
+}
+
+// What the leftovers of a search node would sell for.
+fn leftover_value(
+    surplus: &PersistentMap<ItemId, u32>,
+    listings: &HashMap<ItemId, Item>
+) -> u32 {
+    return surplus.iter()
+        .filter_map(|(item, quantity)| {
+            Some(profit::resale_value(listings.get(item)?, *quantity))
+        })
+        .sum();
+}
+
+// The most that the leftovers of any plan could sell for. Leftovers are always
+// used before crafting more of an item, so a plan never has more of an item
+// left over than one batch of its largest recipe, less one.
+fn max_leftover_value(
+    database: &Database,
+    listings: &HashMap<ItemId, Item>
+) -> u32 {
+    return database.materials().values()
+        .filter_map(|material| {
+            let batch = material.recipes().iter()
+                .map(|recipe| recipe.output())
+                .max()?;
+            let item = listings.get(&material.id())?;
+            Some(profit::resale_value(item, batch - 1))
+        })
+        .sum();
+}
+
+fn flatten(
+    solution: &Rc<SearchNode>,
+    targets: &[Target],
+    database: &Database,
+    listings: &HashMap<ItemId, Item>,
+    inventory: &Inventory
+) -> Plan {
+    let mut buys = HashMap::<ItemId, Buy>::new();
+    let mut vendors = HashMap::<ItemId, Vendor>::new();
+    let mut owned = HashMap::<ItemId, Owned>::new();
+
+    let mut target_plans: Vec<TargetPlan> = targets.iter()
+        .map(|target| TargetPlan{
+            target: target.clone(),
+            cost: 0,
+            steps: Vec::new()
+        })
+        .collect();
+
+    // Walking back from the solution visits every ingredient of a craft
+    // before the craft itself, so the inputs of each craft are complete by the
+    // time the craft is reached.
+    let mut inputs = HashMap::<usize, Vec<Step>>::new();
 
     let mut next = solution.clone();
     while let Some(parent) = &next.parent {
-        match &parent.choice {
-            Choice::Craft(_) => { },
+        let step = match &parent.choice {
+            Choice::Craft(craft) => {
+                let mut craft_inputs =
+                    inputs.remove(&parent.node.crafts).unwrap_or_default();
+                craft_inputs.reverse();
+
+                Step::Craft(CraftStep{
+                    craft: craft.clone(),
+                    inputs: craft_inputs
+                })
+            },
+            Choice::Surplus(surplus) => {
+                Step::Surplus(surplus.clone())
+            },
+            Choice::Owned(used) => {
+                target_plans[parent.target].cost += used.cost;
+                let entry = owned.entry(used.item).or_insert_with(
+                    || Owned{quantity: 0, cost: 0, ..used.clone()}
+                );
+                entry.quantity += used.quantity;
+                entry.cost += used.cost;
+                Step::Owned(used.clone())
+            },
             Choice::Buy(buy) => {
-                if let Some(entry) = result.buy.get_mut(&buy.name) {
-                    // println!(
-                    //     "[Buy] Adding to {}: {} x {}",
-                    //     buy.name, buy.quantity, buy.cost
-                    // );
+                target_plans[parent.target].cost += buy.cost;
+                if let Some(entry) = buys.get_mut(&buy.item) {
                     *entry = &*entry + buy;
                 } else {
-                    // println!(
-                    //     "[Buy] Setting for {}: {} x {}",
-                    //     buy.name, buy.quantity, buy.cost
-                    // );
-                    result.buy.insert(buy.name.clone(), buy.clone());
+                    buys.insert(buy.item, buy.clone());
                 }
+
+                Step::Buy(buy.clone())
             },
             Choice::Vendor(vendor) => {
-                if let Some(entry) = result.vendor.get_mut(&vendor.name) {
-                    // println!(
-                    //     "[Vendor] Adding to {}: {} x {}",
-                    //     vendor.name, vendor.quantity, vendor.cost
-                    // );
+                target_plans[parent.target].cost += vendor.cost;
+                if let Some(entry) = vendors.get_mut(&vendor.item) {
                     *entry = &*entry + vendor;
                 } else {
-                    // println!(
-                    //     "[Vendor] Setting for {}: {} x {}",
-                    //     vendor.name, vendor.quantity, vendor.cost
-                    // );
-                    result.vendor.insert(vendor.name.clone(), vendor.clone());
+                    vendors.insert(vendor.item, vendor.clone());
                 }
+
+                Step::Vendor(vendor.clone())
             }
+        };
+
+        if let Some(origin) = parent.origin {
+            push_step(inputs.entry(origin).or_default(), step);
+        } else {
+            push_step(&mut target_plans[parent.target].steps, step);
         }
 
         next = parent.node.clone();
     }
 
-    return result;
+    for target_plan in &mut target_plans {
+        target_plan.steps.reverse();
+    }
+
+    let trees: Vec<Vec<Step>> = target_plans.iter()
+        .map(|target_plan| target_plan.steps.clone())
+        .collect();
+
+    let mut buys: Vec<Buy> = buys.into_values().collect();
+    buys.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut vendors: Vec<Vendor> = vendors.into_values().collect();
+    vendors.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut owned: Vec<Owned> = owned.into_values()
+        .map(|used| Owned{
+            from: inventory.withdraw(used.item, used.quantity),
+            ..used
+        })
+        .collect();
+    owned.sort_by(|a, b| a.name.cmp(&b.name));
+    let opportunity_cost = owned.iter().map(|used| used.cost).sum();
+
+    let mut leftovers: Vec<Surplus> = solution.surplus.iter()
+        .map(|(item, quantity)| Surplus{
+            item: *item,
+            name: database.name(*item),
+            quantity: *quantity,
+            value: listings.get(item)
+                .map(|listings| profit::resale_value(listings, *quantity))
+                .unwrap_or(0)
+        })
+        .collect();
+    leftovers.sort_by(|a, b| a.name.cmp(&b.name));
+    let leftover_value = leftovers.iter().map(|leftover| leftover.value).sum();
+
+    return Plan{
+        total_cost: solution.total_cost,
+        opportunity_cost,
+        leftover_value,
+        targets: target_plans,
+        buys,
+        vendors,
+        crafts: crafting_queue(&trees),
+        owned,
+        leftovers,
+        optimality_gap: 0
+    };
+}
+
+// Merge a craft step and the crafts of its inputs into [crafts]. Inputs that
+// are crafted become [needs] of the craft, and inputs that are taken from the
+// surplus of other crafts become [leftovers] of it, since the crafts that made
+// that surplus might not have been collected yet.
+fn collect_crafts(
+    step: &CraftStep,
+    crafts: &mut Vec<Craft>,
+    needs: &mut Vec<HashSet<usize>>,
+    leftovers: &mut Vec<HashSet<ItemId>>
+) -> usize {
+    let index =
+        if let Some(index) = crafts.iter().position(
+            |c| c.item == step.craft.item && c.recipe == step.craft.recipe
+        ) {
+            crafts[index].crafts += step.craft.crafts;
+            index
+        } else {
+            crafts.push(step.craft.clone());
+            needs.push(HashSet::new());
+            leftovers.push(HashSet::new());
+            crafts.len() - 1
+        };
+
+    for input in &step.inputs {
+        match input {
+            Step::Craft(input) => {
+                let dependency =
+                    collect_crafts(input, crafts, needs, leftovers);
+                if dependency != index {
+                    needs[index].insert(dependency);
+                }
+            },
+            Step::Surplus(surplus) => {
+                leftovers[index].insert(surplus.item);
+            },
+            _ => {}
+        }
+    }
+
+    return index;
+}
+
+// Merge every craft in the trees by item and recipe, and order them so that
+// each craft comes after the crafts of its ingredients, including the crafts
+// whose surplus it uses.
+fn crafting_queue(trees: &[Vec<Step>]) -> Vec<Craft> {
+    let mut crafts = Vec::<Craft>::new();
+    let mut needs = Vec::<HashSet<usize>>::new();
+    let mut leftovers = Vec::<HashSet<ItemId>>::new();
+    for tree in trees {
+        for step in tree {
+            if let Step::Craft(step) = step {
+                collect_crafts(step, &mut crafts, &mut needs, &mut leftovers);
+            }
+        }
+    }
+
+    for (index, items) in leftovers.iter().enumerate() {
+        for (dependency, craft) in crafts.iter().enumerate() {
+            if dependency != index && items.contains(&craft.item) {
+                needs[index].insert(dependency);
+            }
+        }
+    }
+
+    let mut queued = vec![false; crafts.len()];
+    let mut order = Vec::<usize>::new();
+    while order.len() < crafts.len() {
+        let ready = (0..crafts.len()).find(
+            |i| !queued[*i] && needs[*i].iter().all(|n| queued[*n])
+        );
+
+        // Recipe cycles can leave nothing ready, in which case the queue just
+        // carries on with the first craft that has not been queued yet.
+        let next = ready.unwrap_or_else(
+            || (0..crafts.len()).find(|i| !queued[*i]).unwrap()
+        );
+
+        queued[next] = true;
+        order.push(next);
+    }
+
+    return order.into_iter().map(|i| crafts[i].clone()).collect();
+}
+
+// What a complete plan costs, less what its leftovers would sell for if the
+// [options] credit them.
+fn net_cost(
+    node: &SearchNode,
+    listings: &HashMap<ItemId, Item>,
+    options: &Options
+) -> i64 {
+    let mut net_cost = node.total_cost as i64;
+    if options.credit_leftovers {
+        net_cost -= leftover_value(&node.surplus, listings) as i64;
+    }
+
+    return net_cost;
+}
+
+// What the greedy completion has used of the budget so far, including what
+// the search before it used.
+struct Allowance<'a> {
+    options: &'a Options,
+    started: Instant,
+    nodes: usize,
+    memory: usize
+}
+
+impl Allowance<'_> {
+    fn used_up(&self) -> bool {
+        return self.options.out_of_allowance(
+            self.nodes, self.started, self.memory
+        );
+    }
+}
+
+// Complete a plan from a node by always making the most promising choice, and
+// only going back to the next one when a choice runs into a dead end. Dead
+// ends are reported to [blocked] if it has none yet. The nodes that it expands
+// and the memory that it takes up count against the [allowance], and it gives
+// up once that is used up.
+fn complete_greedily(
+    start: Rc<SearchNode>,
+    bounds: &LowerBounds,
+    database: &Database,
+    owned_values: &OwnedValues,
+    blocked: &mut Option<Infeasible>,
+    allowance: &mut Allowance
+) -> Option<Rc<SearchNode>> {
+    let mut visited = HashSet::<SearchState>::new();
+    let mut stack = vec![start];
+    while let Some(node) = stack.pop() {
+        if allowance.used_up() {
+            return None;
+        }
+
+        let state = node.state();
+        if visited.contains(&state) {
+            continue;
+        }
+        allowance.memory += state.footprint();
+        visited.insert(state);
+        allowance.nodes += 1;
+
+        let mut choices = SearchQueue::new(bounds);
+        match SearchNode::expand(&node, &mut choices, database, owned_values) {
+            Expansion::Complete => return Some(node),
+            Expansion::Expanded => {
+                let mut choices: Vec<Rc<SearchNode>> =
+                    std::iter::from_fn(|| choices.pop())
+                    .map(|(choice, _)| choice)
+                    .collect();
+                choices.reverse();
+                allowance.memory += choices.iter()
+                    .map(|choice| choice.footprint())
+                    .sum::<usize>();
+                stack.append(&mut choices);
+            },
+            Expansion::DeadEnd(infeasible) => {
+                blocked.get_or_insert(infeasible);
+            }
+        }
+    }
+
+    return None;
 }
 
+// The targets and everything that could go into crafting them.
+fn reachable_items(targets: &[Target], database: &Database)
+-> HashSet<ItemId> {
+    let mut visit: Vec<ItemId> = targets.iter().map(|t| t.item()).collect();
+    let mut reachable = HashSet::<ItemId>::new();
+    while let Some(next) = visit.pop() {
+        if !reachable.insert(next) {
+            continue;
+        }
+
+        if let Some(material) = database.get(next) {
+            for recipe in material.recipes() {
+                visit.extend(recipe.ingredients().keys());
+            }
+        }
+    }
+
+    return reachable;
+}
+
+/// Find the cheapest plan that acquires all the targets, sharing the Trading
+/// Post order books between them. Items in the `inventory` cost what the
+/// valuation of the `options` says they are worth. Free ones are used before
+/// anything else. If there is no way to acquire the targets, the error names
+/// an item that the most promising plan could not get enough of.
+///
+/// If the search runs out of the budget of the `options`, the plan is the
+/// cheapest one found so far, or else one completed greedily from the most
+/// promising part of the search, along with its [Plan::optimality_gap].
 pub fn plan(
-    targets: &Vec<String>,
-    descriptions: HashMap<String, MaterialDescription>,
-    listings: &HashMap<String, Item>
-) -> bool {
+    targets: &[Target],
+    database: &Database,
+    listings: &HashMap<ItemId, Item>,
+    inventory: &Inventory,
+    options: &Options
+) -> Result<Plan, Infeasible> {
+    // Only owned items that could go into the targets are of any use, and
+    // every other one would only make the search states bigger.
+    let reachable = reachable_items(targets, database);
+    let owned: HashMap<ItemId, u32> = inventory.quantities().into_iter()
+        .filter(|(item, _)| reachable.contains(item))
+        .collect();
+    let owned_values: OwnedValues = owned.keys()
+        .map(|item| (*item, options.valuation.unit_value(listings.get(item))))
+        .collect();
 
     let initial_remaining = targets.iter()
-        .map(|name| Remainder{ name: name.clone(), quantity: 1 })
+        .enumerate()
+        .map(|(index, target)| Remainder{
+            item: target.item(),
+            quantity: target.quantity(),
+            target: index,
+            origin: None,
+            must: target.must()
+        })
         .collect();
 
-    let initial_listings: HashMap<String, VecDeque<Price>> = listings.iter()
-        .map(|(name, item)| (
-            name.clone(),
-            VecDeque::from_iter(item.sells().clone().into_iter())
-        ))
+    let initial_listings: PersistentMap<ItemId, OrderBook> = listings.iter()
+        .map(|(id, item)| (*id, OrderBook::new(item.sells().clone())))
         .collect();
 
-    let mut queue = SearchQueue::new();
-    queue.push(
-        ByAddress(Rc::new(
-            SearchNode{
-                parent: None,
-                remaining: initial_remaining,
-                listings: initial_listings,
-                descriptions: Rc::new(descriptions),
-                total_cost: 0
+    let bounds = LowerBounds::new(database, &initial_listings, &owned_values);
+    let root = Rc::new(SearchNode{
+        parent: None,
+        remaining: initial_remaining,
+        listings: initial_listings,
+        surplus: PersistentMap::new(),
+        owned: owned.into_iter().collect(),
+        crafts: 0,
+        depth: 0,
+        total_cost: 0
+    });
+
+    let mut queue = SearchQueue::new(&bounds);
+    queue.push(root.clone());
+
+    // The search finds plans in order of cost. Crediting leftovers can make a
+    // later plan cheaper than an earlier one, but never by more than the most
+    // that any leftovers could sell for, so the search carries on until no
+    // plan left in the queue could beat the best one found.
+    let max_credit =
+        if options.credit_leftovers {
+            max_leftover_value(database, listings) as i64
+        } else {
+            0
+        };
+
+    // The same state can be reached by making the same choices in another
+    // order. The estimate never drops by more than a choice costs, so the
+    // first time a state comes out of the queue is also the cheapest, and it
+    // is only expanded then.
+    let mut closed = HashSet::<SearchState>::new();
+    let mut closed_footprint = 0;
+
+    // Every branch of the search can run into an item that cannot be
+    // acquired. If they all do, the first one to come out of the queue is
+    // the one reported, since it was the most promising. Branches can also
+    // end by coming back to a state that was already expanded, which only
+    // recipe cycles do without any other choice in between.
+    let mut blocked: Option<Infeasible> = None;
+    let mut cycled: Option<Infeasible> = None;
+
+    let started = Instant::now();
+    let mut expanded = 0;
+    let mut out_of_budget = false;
+
+    let mut best: Option<(Rc<SearchNode>, i64)> = None;
+    loop {
+        let memory = queue.footprint + closed_footprint;
+        if options.out_of_budget(expanded, started, memory) {
+            out_of_budget = true;
+            break;
+        }
+
+        let (next, estimate) = match queue.pop() {
+            Some(next) => next,
+            None => break
+        };
+
+        if let Some((_, best_cost)) = &best {
+            if *best_cost <= estimate as i64 - max_credit {
+                break;
             }
-        )),
-        Reverse(0));
-
-    while let Some((next, _)) = queue.pop() {
-        if !SearchNode::expand(&next, &mut queue) {
-            println!("\n\n____ Plan Result ____");
-            let result = flatten(&next);
-
-            println!("\nFrom Trading Post, buy:");
-            for (_, buy) in &result.buy {
-                println!(
-                    "{}: {} for a total cost of {}",
-                    buy.name, buy.quantity, buy.cost
-                );
+        }
+
+        let state = next.state();
+        if closed.contains(&state) {
+            if cycled.is_none() {
+                cycled = Some(next.blocked_by(database, true));
             }
+            continue;
+        }
+        closed_footprint += state.footprint();
+        closed.insert(state);
+        expanded += 1;
 
-            println!("\nFrom vendors, buy:");
-            for (_, vendor) in &result.vendor {
-                println!(
-                    "{}: {} for a total cost of {}",
-                    vendor.name, vendor.quantity, vendor.cost
-                );
+        match SearchNode::expand(&next, &mut queue, database, &owned_values) {
+            Expansion::Complete => {
+                let net_cost = net_cost(&next, listings, options);
+                let better = match &best {
+                    Some((_, best_cost)) => net_cost < *best_cost,
+                    None => true
+                };
+
+                if better {
+                    best = Some((next.clone(), net_cost));
+                }
+            },
+            Expansion::Expanded => { },
+            Expansion::DeadEnd(infeasible) => {
+                blocked.get_or_insert(infeasible);
             }
-            break;
         }
     }
 
-    return false;
-}
+    // When the budget runs out, no plan can cost less than the most promising
+    // node left in the queue. Without any complete plan to return, one is
+    // made out of the most promising nodes instead.
+    let mut optimality_gap = 0;
+    if out_of_budget {
+        let lower_bound = queue.lowest_estimate()
+            .map(|estimate| estimate as i64 - max_credit);
+
+        let mut allowance = Allowance{
+            options,
+            started,
+            nodes: expanded,
+            memory: queue.footprint + closed_footprint
+        };
+        while best.is_none() && !allowance.used_up() {
+            let (start, _) = match queue.pop() {
+                Some(start) => start,
+                None => break
+            };
+
+            let greedy = complete_greedily(
+                start,
+                &bounds,
+                database,
+                &owned_values,
+                &mut blocked,
+                &mut allowance
+            );
+            best = greedy.map(|node| {
+                let net_cost = net_cost(&node, listings, options);
+                (node, net_cost)
+            });
+        }
 
-pub fn plan_isolated(
-    target: &String,
-    descriptions: HashMap<String, MaterialDescription>,
-    listings: &HashMap<String, Item>
-) -> bool {
-    let mut targets = Vec::<String>::new();
-    targets.push(target.clone());
-    return plan(&targets, descriptions, listings);
-}
\ No newline at end of file
+        if let (Some((_, cost)), Some(lower_bound)) = (&best, lower_bound) {
+            optimality_gap = (cost - lower_bound).max(0) as u32;
+        }
+    }
+
+    let (solution, _) = match best {
+        Some(best) => best,
+        None => return Err(
+            // Without anything more specific to blame, a failed search blames
+            // the first target that it looked at. Only a search with targets
+            // can fail, since one without them is complete right away.
+            blocked.or(cycled)
+                .unwrap_or_else(|| root.blocked_by(database, false))
+        )
+    };
+
+    let plan = flatten(&solution, targets, database, listings, inventory);
+    return Ok(Plan{optimality_gap, ..plan});
+}
@@ -0,0 +1,194 @@
+use crate::database::{Database, ItemId};
+use crate::planning::Plan;
+use crate::targets::{Must, Target};
+use crate::tp_rest::Item;
+
+use std::collections::HashMap;
+
+/// The share of the price that the Trading Post keeps when an item is listed,
+/// in percent.
+pub const LISTING_FEE_PERCENT: u32 = 5;
+
+/// The share of the price that the Trading Post keeps when an item is sold, in
+/// percent.
+pub const EXCHANGE_FEE_PERCENT: u32 = 10;
+
+// The fee on one item is rounded to the nearest copper, and is at least one
+// copper.
+fn fee(price: u32, percent: u32) -> u32 {
+    let fee = (price as u64 * percent as u64 + 50) / 100;
+    return std::cmp::max(fee, 1) as u32;
+}
+
+/// Selling some quantity of an item on the Trading Post.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Sale {
+    unit_price: u32,
+    revenue: u32,
+    fees: u32
+}
+
+impl Sale {
+    fn new(unit_price: u32, quantity: u32, revenue: u32) -> Sale {
+        let fees = quantity * (
+            fee(unit_price, LISTING_FEE_PERCENT)
+            + fee(unit_price, EXCHANGE_FEE_PERCENT)
+        );
+        return Sale{unit_price, revenue, fees: std::cmp::min(fees, revenue)};
+    }
+
+    /// The price per item. When selling to buy orders, this is the lowest
+    /// price that an item is sold at.
+    pub fn unit_price(&self) -> u32 {
+        return self.unit_price;
+    }
+
+    /// What the buyers pay in total.
+    pub fn revenue(&self) -> u32 {
+        return self.revenue;
+    }
+
+    /// The listing and exchange fees that the Trading Post keeps.
+    pub fn fees(&self) -> u32 {
+        return self.fees;
+    }
+
+    /// What the seller gets after fees.
+    pub fn net(&self) -> u32 {
+        return self.revenue - self.fees;
+    }
+}
+
+/// Sell `quantity` of an item right away to its buy orders, starting from the
+/// highest. Returns [None] if the buy orders do not ask for that many.
+pub fn sell_to_buy_orders(item: &Item, quantity: u32) -> Option<Sale> {
+    let mut revenue = 0;
+    let mut fees = 0;
+    let mut lowest = 0;
+    let mut needed = quantity;
+    for tier in item.buys() {
+        if needed == 0 {
+            break;
+        }
+
+        let sold = std::cmp::min(needed, tier.quantity());
+        let sale = Sale::new(tier.unit_price(), sold, sold * tier.unit_price());
+        revenue += sale.revenue;
+        fees += sale.fees;
+        lowest = tier.unit_price();
+        needed -= sold;
+    }
+
+    if 0 < needed {
+        return None;
+    }
+
+    return Some(Sale{unit_price: lowest, revenue, fees});
+}
+
+/// What selling up to `quantity` of an item to its buy orders would earn after
+/// fees. Anything beyond what the buy orders ask for is worth nothing.
+pub fn resale_value(item: &Item, quantity: u32) -> u32 {
+    let demand = item.buys().iter()
+        .fold(0, |demand: u32, tier| demand.saturating_add(tier.quantity()));
+    return sell_to_buy_orders(item, std::cmp::min(quantity, demand))
+        .map(|sale| sale.net())
+        .unwrap_or(0);
+}
+
+/// List `quantity` of an item one copper below its lowest sell listing, and
+/// wait for buyers. Returns [None] if there are no sell listings to price it
+/// against.
+pub fn list(item: &Item, quantity: u32) -> Option<Sale> {
+    let lowest = item.sells().first()?.unit_price();
+    let price = std::cmp::max(lowest.saturating_sub(1), 1);
+    return Some(Sale::new(price, quantity, quantity * price));
+}
+
+/// What crafting a target and selling it on the Trading Post would earn.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TargetProfit {
+    target: Target,
+    cost: u32,
+    instant: Option<Sale>,
+    listed: Option<Sale>
+}
+
+impl TargetProfit {
+    pub fn target(&self) -> &Target {
+        return &self.target;
+    }
+
+    /// What the plan pays to acquire the target.
+    pub fn cost(&self) -> u32 {
+        return self.cost;
+    }
+
+    /// Selling the target to buy orders right away, if there is enough demand.
+    pub fn instant(&self) -> &Option<Sale> {
+        return &self.instant;
+    }
+
+    /// Listing the target for sale, if it has sell listings to price against.
+    pub fn listed(&self) -> &Option<Sale> {
+        return &self.listed;
+    }
+
+    /// What a `sale` earns after fees and the cost of the target.
+    pub fn profit(&self, sale: &Sale) -> i64 {
+        return sale.net() as i64 - self.cost as i64;
+    }
+
+    /// The `profit` of a `sale` as a percentage of the cost. Returns [None] if
+    /// the target cost nothing.
+    pub fn roi(&self, sale: &Sale) -> Option<f64> {
+        if self.cost == 0 {
+            return None;
+        }
+
+        return Some(100.0 * self.profit(sale) as f64 / self.cost as f64);
+    }
+}
+
+/// Require the targets that have recipes to be crafted, unless they already
+/// say how to acquire them, so that the profit of crafting them is not hidden
+/// by buying them.
+pub fn craft_targets(targets: &[Target], database: &Database) -> Vec<Target> {
+    return targets.iter()
+        .map(|target| {
+            let craftable = database.get(target.item())
+                .map(|material| !material.recipes().is_empty())
+                .unwrap_or(false);
+
+            if target.must().is_some() || !craftable {
+                return target.clone();
+            }
+
+            Target::new(
+                target.item(),
+                target.name().clone(),
+                target.quantity(),
+                Some(Must::Craft)
+            )
+        })
+        .collect();
+}
+
+/// Compare the cost of each target in a `plan` with what it sells for on the
+/// Trading Post, according to the target's own `listings`. Targets that
+/// cannot be traded are left out.
+pub fn evaluate(plan: &Plan, listings: &HashMap<ItemId, Item>)
+-> Vec<TargetProfit> {
+    return plan.targets().iter()
+        .filter_map(|target_plan| {
+            let target = target_plan.target();
+            let item = listings.get(&target.item())?;
+            Some(TargetProfit{
+                target: target.clone(),
+                cost: target_plan.cost(),
+                instant: sell_to_buy_orders(item, target.quantity()),
+                listed: list(item, target.quantity())
+            })
+        })
+        .collect();
+}
@@ -0,0 +1,270 @@
+use crate::database::{Database, ItemId};
+use crate::orders;
+use crate::planning::{Plan, Step};
+use crate::profit::{Sale, TargetProfit};
+use crate::tp_rest::Item;
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+fn print_steps(steps: &[Step], depth: usize) {
+    let indent = "  ".repeat(depth);
+    for step in steps {
+        match step {
+            Step::Craft(step) => {
+                println!(
+                    "{}Craft {} x {} (batches: {})",
+                    indent,
+                    step.craft().produced(),
+                    step.craft().name(),
+                    step.craft().crafts()
+                );
+                print_steps(step.inputs(), depth + 1);
+            },
+            Step::Buy(buy) => {
+                println!(
+                    "{}Buy {} x {} from the Trading Post for {}",
+                    indent, buy.quantity(), buy.name(), buy.cost()
+                );
+            },
+            Step::Vendor(vendor) => {
+                println!(
+                    "{}Buy {} x {} from a vendor for {}",
+                    indent, vendor.quantity(), vendor.name(), vendor.cost()
+                );
+            },
+            Step::Surplus(surplus) => {
+                println!(
+                    "{}Use {} x {} left over from crafting",
+                    indent, surplus.quantity(), surplus.name()
+                );
+            },
+            Step::Owned(owned) => {
+                println!(
+                    "{}Use {} x {} that the account owns, worth {}",
+                    indent, owned.quantity(), owned.name(), owned.cost()
+                );
+            }
+        }
+    }
+}
+
+// Describe how long ago a time was, in the largest whole unit.
+fn age(time: SystemTime) -> String {
+    let seconds = SystemTime::now().duration_since(time)
+        .map(|age| age.as_secs())
+        .unwrap_or(0);
+
+    let (count, unit) =
+        if seconds < 60 {
+            (seconds, "second")
+        } else if seconds < 60 * 60 {
+            (seconds / 60, "minute")
+        } else if seconds < 24 * 60 * 60 {
+            (seconds / (60 * 60), "hour")
+        } else {
+            (seconds / (24 * 60 * 60), "day")
+        };
+
+    return format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" });
+}
+
+/// Print the lowest Trading Post price of each item, along with the age of
+/// the oldest listings if it is known when they were `recorded`.
+pub fn print_prices(
+    listings: &HashMap<ItemId, Item>,
+    recorded: Option<SystemTime>,
+    database: &Database
+) {
+    match recorded {
+        Some(recorded) => println!(
+            "\nPrices (the oldest were recorded {} ago):", age(recorded)
+        ),
+        None => println!("\nPrices:")
+    }
+    for (id, item) in listings {
+        let name = database.name(*id);
+        if let Some(price) = item.sells().first() {
+            println!("{} lowest price: {}", name, price.unit_price());
+        } else {
+            println!("{} has no sell listings", name);
+        }
+    }
+}
+
+/// Print a plan as a human readable report, naming the ingredients of crafts
+/// from the `database`.
+pub fn print_plan(plan: &Plan, database: &Database) {
+    println!("\n\n____ Plan Result ____");
+
+    println!("\nFrom Trading Post, buy:");
+    for buy in plan.buys() {
+        println!(
+            "{}: {} for a total cost of {}",
+            buy.name(), buy.quantity(), buy.cost()
+        );
+    }
+
+    println!("\nFrom vendors, buy:");
+    for vendor in plan.vendors() {
+        println!(
+            "{}: {} for a total cost of {}",
+            vendor.name(), vendor.quantity(), vendor.cost()
+        );
+    }
+
+    if !plan.owned().is_empty() {
+        println!("\nFrom the account, take:");
+        for owned in plan.owned() {
+            let from: Vec<String> = owned.from().iter()
+                .map(|w| format!("{} from {}", w.quantity(), w.location()))
+                .collect();
+            println!(
+                "{}: {} worth {} ({})",
+                owned.name(), owned.quantity(), owned.cost(), from.join(", ")
+            );
+        }
+    }
+
+    println!("\nCrafting tree:");
+    for target_plan in plan.targets() {
+        println!("{}", target_plan.target());
+        print_steps(target_plan.steps(), 1);
+    }
+
+    println!("\nCrafting queue:");
+    for (index, craft) in plan.crafts().iter().enumerate() {
+        let mut ingredients: Vec<(String, &u32)> =
+            craft.recipe().ingredients().iter()
+            .map(|(id, quantity)| (database.name(*id), quantity))
+            .collect();
+        ingredients.sort();
+
+        let ingredients: Vec<String> = ingredients.into_iter()
+            .map(|(name, quantity)| format!(
+                "{} x {}", quantity * craft.crafts(), name
+            ))
+            .collect();
+
+        println!(
+            "{}. Craft {} x {} (batches: {}) from {}",
+            index + 1, craft.produced(), craft.name(), craft.crafts(),
+            ingredients.join(", ")
+        );
+    }
+
+    println!("\nCost of each target:");
+    for target_plan in plan.targets() {
+        println!("{}: {}", target_plan.target(), target_plan.cost());
+    }
+    println!("Total cost: {}", plan.total_cost());
+    if 0 < plan.optimality_gap() {
+        println!(
+            "The search ran out of its budget, so a plan could cost up to {} \
+            less",
+            plan.optimality_gap()
+        );
+    }
+    if 0 < plan.opportunity_cost() {
+        println!(
+            "Of which the value of owned items used: {}",
+            plan.opportunity_cost()
+        );
+    }
+
+    if !plan.leftovers().is_empty() {
+        println!("\nLeft over from crafting:");
+        for leftover in plan.leftovers() {
+            println!(
+                "{}: {}, which would sell for {} to buy orders",
+                leftover.name(), leftover.quantity(), leftover.value()
+            );
+        }
+        println!("Total value of the leftovers: {}", plan.leftover_value());
+        println!(
+            "Total cost less the value of the leftovers: {}",
+            plan.total_cost() as i64 - plan.leftover_value() as i64
+        );
+    }
+}
+
+/// Print the buy orders to place for the Trading Post purchases of a plan made
+/// with [orders::with_buy_orders], comparing each with buying instantly from
+/// the sell `listings`. Orders placed at the same price as existing ones are
+/// only filled after them.
+pub fn print_buy_orders(
+    plan: &Plan,
+    listings: &HashMap<ItemId, Item>,
+    offset: i32
+) {
+    println!("\nBuy orders to place:");
+    let mut savings = 0;
+    for buy in plan.buys() {
+        let item = match listings.get(&buy.item()) {
+            Some(item) => item,
+            None => continue
+        };
+
+        let price = match orders::order_price(item, offset) {
+            Some(price) => price,
+            None => {
+                println!(
+                    "{}: buy {} instantly, since no buy order would be cheaper",
+                    buy.name(), buy.quantity()
+                );
+                continue;
+            }
+        };
+
+        let comparison = match orders::instant_cost(item, buy.quantity()) {
+            Some(instant) => {
+                savings += instant - buy.cost();
+                format!("{} less than buying instantly", instant - buy.cost())
+            },
+            None => "more than the sell listings hold".to_string()
+        };
+
+        let ahead = orders::demand_ahead(item, price);
+        let queue =
+            if ahead == 0 {
+                "ahead of every existing order".to_string()
+            } else {
+                format!("behind {} already ordered at {} or more", ahead, price)
+            };
+
+        println!(
+            "{}: {} at {} each for a total of {} ({}), {}",
+            buy.name(), buy.quantity(), price, buy.cost(), comparison, queue
+        );
+    }
+    println!("Saved compared with buying instantly: {}", savings);
+}
+
+fn print_sale(profit: &TargetProfit, sale: &Option<Sale>, how: &str) {
+    match sale {
+        Some(sale) => {
+            let roi = match profit.roi(sale) {
+                Some(roi) => format!("{:.1}%", roi),
+                None => "n/a".to_string()
+            };
+            println!(
+                "  {} at {} each: {} after {} in fees, for a profit of {} \
+                (ROI {})",
+                how, sale.unit_price(), sale.net(), sale.fees(),
+                profit.profit(sale), roi
+            );
+        },
+        None => println!("  {}: not possible with the current listings", how)
+    }
+}
+
+/// Print what each target would earn if it were crafted and sold, either to
+/// buy orders right away or by listing it.
+pub fn print_profits(profits: &[TargetProfit]) {
+    println!("\nProfit of each target:");
+    for profit in profits {
+        println!("{} costs {}", profit.target(), profit.cost());
+        print_sale(profit, profit.instant(), "Sell to buy orders");
+        print_sale(profit, profit.listed(), "List for sale");
+    }
+}
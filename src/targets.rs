@@ -0,0 +1,273 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Unexpected};
+use serde::de::Visitor;
+use serde::de::value::MapAccessDeserializer;
+
+use crate::database::{Database, ItemId, ItemReference};
+use crate::descriptions::starts_entry;
+use crate::error::{LoadError, Problem};
+
+/// Restricts how a target may be acquired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Must {
+    /// The target has to be crafted, even if it could be bought.
+    Craft,
+    /// The target has to be bought from the Trading Post or a vendor.
+    Buy
+}
+
+// Each entry in the targets file is either just an item or a map with the
+// item, the quantity and any per-target options.
+struct TargetEntry {
+    reference: ItemReference,
+    quantity: u32,
+    must: Option<Must>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DetailedEntry {
+    #[serde(alias = "item")]
+    name: ItemReference,
+    #[serde(default)]
+    quantity: Quantity,
+    #[serde(default)]
+    must: Option<Must>
+}
+
+// The quantity of a target, which has to be at least one.
+struct Quantity(u32);
+
+impl Default for Quantity {
+    fn default() -> Quantity {
+        return Quantity(1);
+    }
+}
+
+struct QuantityVisitor;
+
+impl<'de> Visitor<'de> for QuantityVisitor {
+    type Value = Quantity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a quantity of at least one")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Quantity, E> {
+        return match u32::try_from(value) {
+            Ok(0) => Err(E::custom("a target must want at least one item")),
+            Ok(quantity) => Ok(Quantity(quantity)),
+            Err(_) => Err(E::invalid_value(Unexpected::Unsigned(value), &self))
+        };
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+    -> Result<Quantity, D::Error> {
+        return deserializer.deserialize_u32(QuantityVisitor);
+    }
+}
+
+struct TargetEntryVisitor;
+
+impl<'de> Visitor<'de> for TargetEntryVisitor {
+    type Value = TargetEntry;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an item, or a map with [name] and optional [quantity] \
+                     and [must] fields")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<TargetEntry, E> {
+        return Ok(TargetEntry{
+            reference: ItemReference::Text(value.to_string()),
+            quantity: 1,
+            must: None
+        });
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<TargetEntry, E> {
+        let id = ItemId::try_from(value).map_err(
+            |_| E::invalid_value(Unexpected::Unsigned(value), &"an item ID")
+        )?;
+
+        return Ok(TargetEntry{
+            reference: ItemReference::Id(id),
+            quantity: 1,
+            must: None
+        });
+    }
+
+    // Maps are read strictly, so that a misspelled field or value is reported
+    // where it is instead of the entry as a whole failing to match.
+    fn visit_map<A: MapAccess<'de>>(self, map: A)
+    -> Result<TargetEntry, A::Error> {
+        let entry = DetailedEntry::deserialize(MapAccessDeserializer::new(map))?;
+        return Ok(TargetEntry{
+            reference: entry.name,
+            quantity: entry.quantity.0,
+            must: entry.must
+        });
+    }
+}
+
+impl<'de> Deserialize<'de> for TargetEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+    -> Result<TargetEntry, D::Error> {
+        return deserializer.deserialize_any(TargetEntryVisitor);
+    }
+}
+
+/// An item to plan for, along with how many are wanted.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Target {
+    item: ItemId,
+    name: String,
+    quantity: u32,
+    must: Option<Must>
+}
+
+impl Target {
+    pub fn new(
+        item: ItemId,
+        name: String,
+        quantity: u32,
+        must: Option<Must>
+    ) -> Target {
+        return Target{item, name, quantity, must};
+    }
+
+    pub fn item(&self) -> ItemId {
+        return self.item;
+    }
+
+    /// The name of the item, for display.
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    pub fn quantity(&self) -> u32 {
+        return self.quantity;
+    }
+
+    pub fn must(&self) -> Option<Must> {
+        return self.must;
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} x {}", self.quantity, self.name)?;
+        match self.must {
+            Some(Must::Craft) => write!(f, " (must craft)"),
+            Some(Must::Buy) => write!(f, " (must buy)"),
+            None => Ok(())
+        }
+    }
+}
+
+// Read each entry of a block style YAML list on its own, so that one bad
+// entry does not hide problems in the rest of the file. The dash of every
+// entry is blanked out and the piece padded with empty lines, so that the
+// entry parses as a plain value with the line and column numbers of the whole
+// file. Other layouts are left to be read as a whole.
+fn split_entries(source: &str) -> Option<Vec<Result<TargetEntry, Problem>>> {
+    let lines: Vec<&str> = source.lines().collect();
+    let starts: Vec<usize> = (0..lines.len())
+        .filter(|i| starts_entry(lines[*i]))
+        .collect();
+
+    let block_sequence = starts.iter().all(
+        |i| lines[*i] == "-" || lines[*i].starts_with("- ")
+    );
+    if !block_sequence || starts.is_empty() {
+        return None;
+    }
+
+    let mut entries = Vec::<Result<TargetEntry, Problem>>::new();
+    for (index, start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or(lines.len());
+        let piece = "\n".repeat(*start)
+            + " "
+            + &lines[*start][1..]
+            + "\n"
+            + &lines[*start + 1..end].join("\n");
+
+        let deserializer = serde_yaml::Deserializer::from_str(&piece);
+        entries.push(
+            serde_path_to_error::deserialize(deserializer)
+                .map_err(Problem::from_yaml)
+        );
+    }
+
+    return Some(entries);
+}
+
+/// Load a list of targets. Each entry may either be an item or a map with the
+/// `name`, `quantity` and `must` fields. Items may be given by name, by ID or
+/// by chat link, and must be described in the `database`. Every problem in
+/// the file is reported, each with its entry and location.
+pub fn load_file(path: &std::path::Path, database: &Database)
+-> Result<Vec<Target>, LoadError> {
+    let source = std::fs::read_to_string(path).map_err(
+        |error| LoadError::Io{file: path.to_path_buf(), error}
+    )?;
+
+    let mut targets = Vec::<Target>::new();
+    let mut problems = Vec::<Problem>::new();
+    let entries = match split_entries(&source) {
+        Some(entries) => entries,
+        None => {
+            let deserializer = serde_yaml::Deserializer::from_str(&source);
+            let entries: Vec<TargetEntry> =
+                serde_path_to_error::deserialize(deserializer).map_err(
+                    |error| LoadError::Invalid{
+                        file: path.to_path_buf(),
+                        problems: vec![Problem::from_yaml(error)]
+                    }
+                )?;
+            entries.into_iter().map(Ok).collect()
+        }
+    };
+
+    for (index, entry) in entries.into_iter().enumerate() {
+        let TargetEntry{reference, quantity, must} = match entry {
+            Ok(entry) => entry,
+            Err(problem) => {
+                let path = match problem.path().as_str() {
+                    "" => format!("[{}]", index),
+                    path => format!("[{}].{}", index, path)
+                };
+                problems.push(Problem::new(
+                    path,
+                    problem.line(),
+                    problem.column(),
+                    problem.message().clone()
+                ));
+                continue;
+            }
+        };
+
+        match reference.resolve(database) {
+            Some(item) => targets.push(
+                Target{item, name: database.name(item), quantity, must}
+            ),
+            None => problems.push(Problem::new(
+                format!("[{}]", index),
+                None,
+                None,
+                format!("no described item matches {}", reference)
+            ))
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(targets);
+    }
+
+    return Err(LoadError::Invalid{file: path.to_path_buf(), problems});
+}
@@ -1,10 +1,199 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use restson::{RestClient, RestPath, Error};
+use restson::{RestClient, RestPath};
+use serde::de::DeserializeOwned;
 
-use crate::descriptions::MaterialDescription;
+use crate::database::{Database, ItemId};
 
+/// The official Guild Wars 2 API, used unless another base URL is given.
+pub const API_URL: &str = "https://api.guildwars2.com";
+
+/// The most IDs that the API accepts in a single request.
+pub const MAX_IDS_PER_REQUEST: usize = 200;
+
+/// How many requests the API accepts per minute from one client.
+pub const REQUESTS_PER_MINUTE: u32 = 600;
+
+/// How many requests the API accepts in a burst before it starts limiting.
+pub const REQUEST_BURST: u32 = 300;
+
+/// How many times a request is tried before giving up on it.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+// How long to wait before the first retry. Every retry after that waits twice
+// as long as the one before.
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A failed request to the Guild Wars 2 API.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be made, e.g. because the base URL is invalid.
+    Request(String),
+    /// The API could not be reached, or did not answer in time.
+    Connection(String),
+    /// The API answered with an error status.
+    Status {
+        status: u16,
+        text: String
+    },
+    /// The answer of the API could not be read.
+    InvalidResponse(String)
+}
+
+impl Error {
+    /// Whether the same request might succeed if it is tried again later.
+    pub fn is_transient(&self) -> bool {
+        return match self {
+            Error::Connection(_) => true,
+            Error::Status{status, ..} => *status == 429 || *status >= 500,
+            _ => false
+        };
+    }
+}
+
+impl From<restson::Error> for Error {
+    fn from(error: restson::Error) -> Error {
+        match error {
+            restson::Error::HttpError(status, body) => {
+                // The API explains its errors in a [text] field
+                let text = serde_json::from_str::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|value| value["text"].as_str().map(String::from))
+                    .unwrap_or(body);
+                return Error::Status{status, text};
+            },
+            restson::Error::DeserializeParseError(error, _) => {
+                return Error::InvalidResponse(error.to_string());
+            },
+            restson::Error::HyperError(ref inner) => {
+                return Error::Connection(format!("{}: {}", error, inner));
+            },
+            restson::Error::IoError(ref inner) => {
+                return Error::Connection(format!("{}: {}", error, inner));
+            },
+            restson::Error::RequestError | restson::Error::TimeoutError => {
+                return Error::Connection(error.to_string());
+            },
+            _ => {
+                return Error::Request(error.to_string());
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Request(message) => write!(f, "{}", message),
+            Error::Connection(message) => write!(f, "{}", message),
+            Error::Status{status, text} => {
+                write!(f, "The API answered with status {}: {}", status, text)
+            },
+            Error::InvalidResponse(message) => {
+                write!(f, "The API gave an invalid answer: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
+/// A token bucket that keeps requests within the API's rate limit. Requests
+/// wait for a token, and the tokens are refilled at a steady rate up to the
+/// burst size.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    // The tokens that are left, and when they were last refilled
+    state: Mutex<(f64, Instant)>
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> RateLimiter {
+        return RateLimiter{
+            per_second: per_minute as f64 / 60.0,
+            burst: burst as f64,
+            state: Mutex::new((burst as f64, Instant::now()))
+        };
+    }
+
+    /// Wait until another request may be made.
+    pub fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let refill = (now - state.1).as_secs_f64() * self.per_second;
+                state.0 = (state.0 + refill).min(self.burst);
+                state.1 = now;
+
+                if 1.0 <= state.0 {
+                    state.0 -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - state.0) / self.per_second)
+            };
+
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+/// The rate limiter shared by every request that this module makes.
+pub fn rate_limiter() -> &'static RateLimiter {
+    return RATE_LIMITER.get_or_init(
+        || RateLimiter::new(REQUESTS_PER_MINUTE, REQUEST_BURST)
+    );
+}
+
+/// Make a client for the API at `api_url`.
+pub fn connect(api_url: &str) -> Result<RestClient, Error> {
+    let mut client = RestClient::new(api_url)?;
+    client.set_timeout(TIMEOUT);
+    return Ok(client);
+}
+
+/// Make a client for the API at `api_url` that authenticates with `api_key`,
+/// for the endpoints that need access to an account.
+pub fn connect_with_key(api_url: &str, api_key: &str)
+-> Result<RestClient, Error> {
+    let mut client = connect(api_url)?;
+    client.set_header("Authorization", &format!("Bearer {}", api_key))?;
+    return Ok(client);
+}
+
+// Make a GET request within the rate limit, retrying with exponential backoff
+// for as long as it fails in a way that might be temporary.
+fn get<U: Copy, T: DeserializeOwned + RestPath<U>>(
+    client: &mut RestClient,
+    params: U
+) -> Result<T, Error> {
+    let mut backoff = FIRST_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        rate_limiter().acquire();
+        match client.get::<U, T>(params).map_err(Error::from) {
+            Err(error) if error.is_transient() && attempt < MAX_ATTEMPTS => {
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            },
+            result => return result
+        }
+    }
+}
+
+/// One price tier of a Trading Post order book.
 #[derive(Serialize,Deserialize,Clone,Debug)]
 pub struct Price {
     listings: u32,
@@ -13,6 +202,10 @@ pub struct Price {
 }
 
 impl Price {
+    pub fn new(listings: u32, unit_price: u32, quantity: u32) -> Price {
+        return Price{listings, unit_price, quantity};
+    }
+
     pub fn unit_price(&self) -> u32 {
         return self.unit_price;
     }
@@ -21,6 +214,8 @@ impl Price {
         return self.quantity;
     }
 
+    /// Get this tier with `quantity` fewer items left in it. The quantity
+    /// must be less than what the tier holds.
     pub fn copy_reduced_by(&self, quantity: u32) -> Price {
         if quantity < self.quantity {
             return Price{
@@ -37,6 +232,7 @@ impl Price {
     }
 }
 
+/// The Trading Post order book of an item.
 #[derive(Serialize,Deserialize,Clone,Debug)]
 pub struct Item {
     id: u32,
@@ -45,92 +241,541 @@ pub struct Item {
 }
 
 impl Item {
+    pub fn new(id: u32, buys: Vec<Price>, sells: Vec<Price>) -> Item {
+        return Item{id, buys, sells};
+    }
+
     pub fn id(&self) -> u32 {
         return self.id;
     }
 
+    /// Buy orders, starting from the highest price.
     pub fn buys(&self) -> &Vec<Price> {
         return &self.buys;
     }
 
+    /// Sell listings, starting from the lowest price.
     pub fn sells(&self) -> &Vec<Price> {
         return &self.sells;
     }
 }
 
-struct ListingRequest {
-    ids: Vec<u32>,
-    dict: HashMap<u32, String>
+struct ListingRequest<'a> {
+    ids: &'a [u32]
 }
 
-impl ListingRequest {
-    fn new(descriptions: &HashMap<String, MaterialDescription>) -> ListingRequest {
-        let mut ids = Vec::<u32>::new();
-        let mut dict = HashMap::<u32, String>::new();
-        for (name, desc) in descriptions {
-            if let Some(id) = desc.post_id() {
-                ids.push(id.clone());
-                dict.insert(id.clone(), name.clone());
-            }
+impl RestPath<&ListingRequest<'_>> for Vec<Item> {
+    fn get_path(param: &ListingRequest) -> Result<String, restson::Error> {
+        Ok(format!("v2/commerce/listings?ids={}", join_ids(param.ids)))
+    }
+}
+
+#[derive(Deserialize)]
+struct TopPrice {
+    quantity: u32,
+    unit_price: u32
+}
+
+impl TopPrice {
+    fn tiers(&self) -> Vec<Price> {
+        if self.quantity == 0 {
+            return Vec::new();
         }
 
-        return ListingRequest{ids: ids, dict: dict};
+        // The number of listings is not part of the prices API
+        return vec![Price::new(0, self.unit_price, self.quantity)];
     }
 }
 
-impl RestPath<&ListingRequest> for Vec<Item> {
-    fn get_path(param: &ListingRequest) -> Result<String, Error> {
-        let id_str: String =
-            param.ids.iter()
-            .map(|&id| id.to_string())
-            .collect::<Vec<String>>().join(",");
+#[derive(Deserialize)]
+struct PriceInfo {
+    id: u32,
+    buys: TopPrice,
+    sells: TopPrice
+}
+
+impl From<PriceInfo> for Item {
+    // Every order is treated as if it were at the best price, which is only
+    // exact for as many items as the best tier holds.
+    fn from(info: PriceInfo) -> Item {
+        return Item::new(info.id, info.buys.tiers(), info.sells.tiers());
+    }
+}
+
+struct PriceRequest<'a> {
+    ids: &'a [u32]
+}
+
+impl RestPath<&PriceRequest<'_>> for Vec<PriceInfo> {
+    fn get_path(param: &PriceRequest) -> Result<String, restson::Error> {
+        Ok(format!("v2/commerce/prices?ids={}", join_ids(param.ids)))
+    }
+}
+
+fn join_ids(ids: &[u32]) -> String {
+    return ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>().join(",");
+}
+
+fn default_ingredient_type() -> String {
+    return "Item".to_string();
+}
+
+/// An ingredient of a recipe from the recipes API.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct IngredientInfo {
+    #[serde(rename = "type", default = "default_ingredient_type")]
+    kind: String,
+    #[serde(alias = "item_id")]
+    id: u32,
+    count: u32
+}
+
+impl IngredientInfo {
+    /// Whether the ingredient is an item, rather than a currency or a guild
+    /// upgrade.
+    pub fn is_item(&self) -> bool {
+        return self.kind == "Item";
+    }
+
+    pub fn id(&self) -> u32 {
+        return self.id;
+    }
+
+    pub fn count(&self) -> u32 {
+        return self.count;
+    }
+}
+
+/// A recipe from the recipes API.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct RecipeInfo {
+    id: u32,
+    output_item_id: u32,
+    output_item_count: u32,
+    ingredients: Vec<IngredientInfo>
+}
+
+impl RecipeInfo {
+    pub fn id(&self) -> u32 {
+        return self.id;
+    }
+
+    pub fn output_item_id(&self) -> u32 {
+        return self.output_item_id;
+    }
+
+    pub fn output_item_count(&self) -> u32 {
+        return self.output_item_count;
+    }
+
+    pub fn ingredients(&self) -> &Vec<IngredientInfo> {
+        return &self.ingredients;
+    }
+}
 
-        Ok(format!("v2/commerce/listings?ids={}", id_str))
+/// An item from the items API.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct ItemInfo {
+    id: u32,
+    name: String,
+    #[serde(default)]
+    flags: Vec<String>
+}
+
+impl ItemInfo {
+    pub fn id(&self) -> u32 {
+        return self.id;
+    }
+
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
+    /// Whether the item can be traded on the Trading Post.
+    pub fn is_tradable(&self) -> bool {
+        return !self.flags.iter().any(
+            |flag| flag == "AccountBound" || flag == "SoulbindOnAcquire"
+        );
     }
 }
 
-pub fn get_listings(descriptions: &HashMap<String, MaterialDescription>)
--> HashMap<String, Item> {
-    let mut client = RestClient::new("https://api.guildwars2.com").unwrap();
-    let request = ListingRequest::new(&descriptions);
-    let response: Vec<Item> = client.get(&request).unwrap();
+struct RecipeSearch {
+    output: u32
+}
 
-    let mut result = HashMap::<String, Item>::new();
-    for item in response {
-        result.insert(request.dict.get(&item.id()).unwrap().clone(), item.clone());
+impl RestPath<&RecipeSearch> for Vec<u32> {
+    fn get_path(param: &RecipeSearch) -> Result<String, restson::Error> {
+        Ok(format!("v2/recipes/search?output={}", param.output))
+    }
+}
+
+struct RecipeRequest<'a> {
+    ids: &'a [u32]
+}
+
+impl RestPath<&RecipeRequest<'_>> for Vec<RecipeInfo> {
+    fn get_path(param: &RecipeRequest) -> Result<String, restson::Error> {
+        Ok(format!("v2/recipes?ids={}", join_ids(param.ids)))
+    }
+}
+
+struct ItemRequest<'a> {
+    ids: &'a [u32]
+}
+
+impl RestPath<&ItemRequest<'_>> for Vec<ItemInfo> {
+    fn get_path(param: &ItemRequest) -> Result<String, restson::Error> {
+        Ok(format!("v2/items?ids={}", join_ids(param.ids)))
+    }
+}
+
+/// Find the IDs of every recipe that crafts the item `output`.
+pub fn search_recipes(client: &mut RestClient, output: u32)
+-> Result<Vec<u32>, Error> {
+    return get(client, &RecipeSearch{output});
+}
+
+/// Fetch recipes by their IDs. Unknown IDs are left out of the result.
+pub fn get_recipes(client: &mut RestClient, ids: &[u32])
+-> Result<Vec<RecipeInfo>, Error> {
+    let mut recipes = Vec::<RecipeInfo>::new();
+    for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+        let response: Vec<RecipeInfo> =
+            get(client, &RecipeRequest{ids: chunk})?;
+        recipes.extend(response);
+    }
+
+    return Ok(recipes);
+}
+
+/// Fetch items by their IDs. Unknown IDs are left out of the result.
+pub fn get_items(client: &mut RestClient, ids: &[u32])
+-> Result<Vec<ItemInfo>, Error> {
+    let mut items = Vec::<ItemInfo>::new();
+    for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+        let response: Vec<ItemInfo> = get(client, &ItemRequest{ids: chunk})?;
+        items.extend(response);
+    }
+
+    return Ok(items);
+}
+
+/// A stack of items somewhere in an account.
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct ItemStack {
+    id: u32,
+    count: u32
+}
+
+impl ItemStack {
+    pub fn id(&self) -> u32 {
+        return self.id;
+    }
+
+    pub fn count(&self) -> u32 {
+        return self.count;
+    }
+}
+
+#[derive(Deserialize)]
+struct Bag {
+    // Empty slots are null
+    inventory: Vec<Option<ItemStack>>
+}
+
+#[derive(Deserialize)]
+struct CharacterInventory {
+    // Empty bag slots are null
+    bags: Vec<Option<Bag>>
+}
+
+struct MaterialStorageRequest;
+
+impl RestPath<&MaterialStorageRequest> for Vec<ItemStack> {
+    fn get_path(_: &MaterialStorageRequest) -> Result<String, restson::Error> {
+        Ok("v2/account/materials".to_string())
+    }
+}
+
+struct BankRequest;
+
+impl RestPath<&BankRequest> for Vec<Option<ItemStack>> {
+    fn get_path(_: &BankRequest) -> Result<String, restson::Error> {
+        Ok("v2/account/bank".to_string())
+    }
+}
+
+struct SharedInventoryRequest;
+
+impl RestPath<&SharedInventoryRequest> for Vec<Option<ItemStack>> {
+    fn get_path(_: &SharedInventoryRequest) -> Result<String, restson::Error> {
+        Ok("v2/account/inventory".to_string())
+    }
+}
+
+struct CharactersRequest;
+
+impl RestPath<&CharactersRequest> for Vec<String> {
+    fn get_path(_: &CharactersRequest) -> Result<String, restson::Error> {
+        Ok("v2/characters".to_string())
+    }
+}
+
+struct CharacterInventoryRequest<'a> {
+    name: &'a str
+}
+
+impl RestPath<&CharacterInventoryRequest<'_>> for CharacterInventory {
+    fn get_path(param: &CharacterInventoryRequest)
+    -> Result<String, restson::Error> {
+        Ok(format!("v2/characters/{}/inventory", encode_name(param.name)))
+    }
+}
+
+// Character names may have spaces and accented letters, which have to be
+// percent-encoded in a path.
+fn encode_name(name: &str) -> String {
+    return name.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect();
+}
+
+/// Fetch the material storage of the account that `client` authenticates as.
+/// Materials that the account has none of are left out.
+pub fn get_material_storage(client: &mut RestClient)
+-> Result<Vec<ItemStack>, Error> {
+    let stacks: Vec<ItemStack> = get(client, &MaterialStorageRequest)?;
+    return Ok(stacks.into_iter().filter(|stack| 0 < stack.count).collect());
+}
+
+/// Fetch the stacks in the bank of the account that `client` authenticates
+/// as.
+pub fn get_bank(client: &mut RestClient) -> Result<Vec<ItemStack>, Error> {
+    let slots: Vec<Option<ItemStack>> = get(client, &BankRequest)?;
+    return Ok(slots.into_iter().flatten().collect());
+}
+
+/// Fetch the stacks in the shared inventory slots of the account that
+/// `client` authenticates as.
+pub fn get_shared_inventory(client: &mut RestClient)
+-> Result<Vec<ItemStack>, Error> {
+    let slots: Vec<Option<ItemStack>> = get(client, &SharedInventoryRequest)?;
+    return Ok(slots.into_iter().flatten().collect());
+}
+
+/// Fetch the names of the characters of the account that `client`
+/// authenticates as.
+pub fn get_characters(client: &mut RestClient)
+-> Result<Vec<String>, Error> {
+    return get(client, &CharactersRequest);
+}
+
+/// Fetch the stacks in the bags of the character called `name`.
+pub fn get_character_inventory(client: &mut RestClient, name: &str)
+-> Result<Vec<ItemStack>, Error> {
+    let inventory: CharacterInventory =
+        get(client, &CharacterInventoryRequest{name})?;
+    return Ok(
+        inventory.bags.into_iter()
+            .flatten()
+            .flat_map(|bag| bag.inventory.into_iter().flatten())
+            .collect()
+    );
+}
+
+/// Look up the names of the described items that are only known by their ID,
+/// and lowercase them to match the descriptions file.
+pub fn fetch_names(api_url: &str, database: &mut Database)
+-> Result<(), Error> {
+    let ids = database.unnamed();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut client = connect(api_url)?;
+    for item in get_items(&mut client, &ids)? {
+        database.set_name(item.id(), item.name().to_lowercase());
+    }
+
+    return Ok(());
+}
+
+/// Trading Post listings that were fetched in several requests. A request
+/// that fails does not stop the others, so some listings may be missing.
+#[derive(Debug, Default)]
+pub struct Listings {
+    listings: HashMap<ItemId, Item>,
+    failed: HashSet<ItemId>,
+    errors: Vec<Error>
+}
+
+impl Listings {
+    /// Listings that are complete, since nothing failed.
+    pub fn new(listings: HashMap<ItemId, Item>) -> Listings {
+        return Listings{listings, ..Default::default()};
+    }
+
+    /// The listings of every item that could be fetched.
+    pub fn listings(&self) -> &HashMap<ItemId, Item> {
+        return &self.listings;
+    }
+
+    pub fn into_listings(self) -> HashMap<ItemId, Item> {
+        return self.listings;
+    }
+
+    /// The items whose listings could not be fetched.
+    pub fn failed(&self) -> &HashSet<ItemId> {
+        return &self.failed;
+    }
+
+    /// Why each failed request failed.
+    pub fn errors(&self) -> &Vec<Error> {
+        return &self.errors;
+    }
+
+    /// Add listings that were found elsewhere, such as in a cache, for any
+    /// items that are missing.
+    pub fn fill_in(&mut self, listings: HashMap<ItemId, Item>) {
+        for (item, listing) in listings {
+            self.listings.entry(item).or_insert(listing);
+        }
+    }
+}
+
+// The commerce APIs leave out IDs that cannot be traded, and answer with an
+// error if none of the IDs in a request can be traded.
+fn get_listings_chunk(api_url: &str, ids: &[u32])
+-> Result<Vec<Item>, Error> {
+    let mut client = connect(api_url)?;
+    return match get(&mut client, &ListingRequest{ids}) {
+        Err(Error::Status{status: 404, ..}) => Ok(Vec::new()),
+        result => result
+    };
+}
+
+fn get_prices_chunk(api_url: &str, ids: &[u32])
+-> Result<Vec<Item>, Error> {
+    let mut client = connect(api_url)?;
+    return match get::<_, Vec<PriceInfo>>(&mut client, &PriceRequest{ids}) {
+        Ok(prices) => Ok(prices.into_iter().map(Item::from).collect()),
+        Err(Error::Status{status: 404, ..}) => Ok(Vec::new()),
+        Err(error) => Err(error)
+    };
+}
+
+// Fetch the order books of the [items] that have a [post_id] with one request
+// for each chunk of IDs, all running in parallel.
+fn get_chunked(
+    api_url: &str,
+    database: &Database,
+    items: &HashSet<ItemId>,
+    get_chunk: fn(&str, &[u32]) -> Result<Vec<Item>, Error>
+) -> Listings {
+    let mut post_ids = Vec::<u32>::new();
+    let mut dict = HashMap::<u32, ItemId>::new();
+    for item in items {
+        if let Some(post_id) = database.get(*item).and_then(|m| *m.post_id()) {
+            post_ids.push(post_id);
+            dict.insert(post_id, *item);
+        }
+    }
+    post_ids.sort();
+
+    let chunks: Vec<&[u32]> = post_ids.chunks(MAX_IDS_PER_REQUEST).collect();
+    let responses: Vec<Result<Vec<Item>, Error>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = chunks.iter()
+            .map(|chunk| scope.spawn(move || get_chunk(api_url, chunk)))
+            .collect();
+
+        return workers.into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect();
+    });
+
+    let mut result = Listings::default();
+    for (chunk, response) in chunks.iter().zip(responses) {
+        match response {
+            Ok(response) => {
+                for item in response {
+                    if let Some(id) = dict.get(&item.id()) {
+                        result.listings.insert(*id, item);
+                    }
+                }
+            },
+            Err(error) => {
+                result.failed.extend(chunk.iter().map(|post_id| dict[post_id]));
+                result.errors.push(error);
+            }
+        }
     }
 
     return result;
 }
 
-pub fn get_listings_for_targets(
-    mut targets: Vec<String>,
-    descriptions: &HashMap<String, MaterialDescription>
-) -> HashMap<String, Item> {
+/// Fetch the Trading Post listings of the `items` that have a `post_id` from
+/// the API at `api_url`, keyed by item ID. The requests are split up to stay
+/// within the API's limit on IDs per request, and run in parallel. Items that
+/// cannot be traded have no listings in the result.
+pub fn get_listings(
+    api_url: &str,
+    database: &Database,
+    items: &HashSet<ItemId>
+) -> Listings {
+    return get_chunked(api_url, database, items, get_listings_chunk);
+}
 
-    let mut visit = HashSet::<String>::new();
+/// Same as [get_listings], but only fetches the best buy and sell price of
+/// each item, along with the total quantity ordered. This takes less data,
+/// but treats every order as if it were at the best price.
+pub fn get_prices(
+    api_url: &str,
+    database: &Database,
+    items: &HashSet<ItemId>
+) -> Listings {
+    return get_chunked(api_url, database, items, get_prices_chunk);
+}
 
-    let mut want_listings_for = HashMap::<String, MaterialDescription>::new();
+/// The targets and everything that could go into crafting them that can be
+/// traded on the Trading Post.
+pub fn tradable_items(mut targets: Vec<ItemId>, database: &Database)
+-> HashSet<ItemId> {
+    let mut visit = HashSet::<ItemId>::new();
+    let mut tradable = HashSet::<ItemId>::new();
     while let Some(next) = targets.pop() {
 
-        if !visit.insert(next.clone()) {
+        if !visit.insert(next) {
             continue;
         }
 
-        if let Some(description) = descriptions.get(&next) {
-            for recipe in description.recipes() {
-                for (item_name, _) in recipe {
-                    targets.push(item_name.clone());
-                }
+        if let Some(material) = database.get(next) {
+            for recipe in material.recipes() {
+                targets.extend(recipe.ingredients().keys());
             }
 
-            if let Some(_) = description.post_id() {
-                want_listings_for.insert(next, description.clone());
+            if material.post_id().is_some() {
+                tradable.insert(next);
             }
-        } else {
-            panic!("Missing description for material [{}]", next);
         }
     }
 
-    return get_listings(&want_listings_for);
-}
\ No newline at end of file
+    return tradable;
+}
+
+/// Fetch the Trading Post listings of the targets and of everything that could
+/// go into crafting them.
+pub fn get_listings_for_targets(
+    api_url: &str,
+    targets: Vec<ItemId>,
+    database: &Database
+) -> Listings {
+    return get_listings(api_url, database, &tradable_items(targets, database));
+}
@@ -0,0 +1,191 @@
+use crate::database::{self, Database, ItemId};
+use crate::descriptions::MaterialDescription;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A problem in a material descriptions database that would stop the planner
+/// from working with some items.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// A recipe of `item` uses an `ingredient` that is not described.
+    MissingDescription {
+        item: String,
+        ingredient: String
+    },
+    /// The item has no `id` or `post_id`, and is not keyed by its ID.
+    MissingId(String),
+    /// The `item` has the same `id` as another item.
+    DuplicateId {
+        item: String,
+        id: ItemId,
+        duplicate_of: String
+    },
+    /// Each item of the cycle is an ingredient of the one before it, and the
+    /// first item is an ingredient of the last.
+    Cycle(Vec<String>),
+    /// The item has no Trading Post ID, no vendor price and no recipe.
+    Unobtainable(String),
+    /// Two recipes of `item` are the same. Recipes are counted from zero.
+    DuplicateRecipe {
+        item: String,
+        recipe: usize,
+        duplicate_of: usize
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::MissingDescription{item, ingredient} => write!(
+                f, "[{}] uses [{}] which has no description", item, ingredient
+            ),
+            Issue::MissingId(item) => write!(
+                f, "[{}] has no id or post_id", item
+            ),
+            Issue::DuplicateId{item, id, duplicate_of} => write!(
+                f, "[{}] has the same ID {} as [{}]", item, id, duplicate_of
+            ),
+            Issue::Cycle(items) => {
+                let mut cycle: Vec<String> = items.iter()
+                    .map(|item| format!("[{}]", item))
+                    .collect();
+                cycle.push(format!("[{}]", items[0]));
+                write!(f, "Recipe cycle: {}", cycle.join(" -> "))
+            },
+            Issue::Unobtainable(item) => write!(
+                f, "[{}] has no post_id, no vendor and no recipe", item
+            ),
+            Issue::DuplicateRecipe{item, recipe, duplicate_of} => write!(
+                f, "Recipe #{} of [{}] is the same as recipe #{}",
+                recipe, item, duplicate_of
+            )
+        }
+    }
+}
+
+fn find_cycles(
+    item: ItemId,
+    database: &Database,
+    path: &mut Vec<ItemId>,
+    finished: &mut HashSet<ItemId>,
+    cycles: &mut Vec<Vec<String>>
+) {
+    if finished.contains(&item) {
+        return;
+    }
+
+    if let Some(start) = path.iter().position(|visited| *visited == item) {
+        // Rotate the cycle to begin with its smallest name, so the same cycle
+        // is only reported once no matter where the search entered it.
+        let mut cycle: Vec<String> = path[start..].iter()
+            .map(|id| database.name(*id))
+            .collect();
+        let first = cycle.iter().enumerate()
+            .min_by(|a, b| a.1.cmp(b.1)).unwrap().0;
+        cycle.rotate_left(first);
+        if !cycles.contains(&cycle) {
+            cycles.push(cycle);
+        }
+        return;
+    }
+
+    if let Some(material) = database.get(item) {
+        path.push(item);
+        for recipe in material.recipes() {
+            let mut ingredients: Vec<(String, ItemId)> =
+                recipe.ingredients().keys()
+                .map(|id| (database.name(*id), *id))
+                .collect();
+            ingredients.sort();
+            for (_, ingredient) in ingredients {
+                find_cycles(ingredient, database, path, finished, cycles);
+            }
+        }
+        path.pop();
+    }
+
+    finished.insert(item);
+}
+
+/// Check a material descriptions database for every problem at once.
+pub fn validate(descriptions: &HashMap<String, MaterialDescription>)
+-> Vec<Issue> {
+    let database = Database::new(descriptions);
+    let mut issues = Vec::<Issue>::new();
+
+    let mut items: Vec<&String> = descriptions.keys().collect();
+    items.sort();
+
+    let mut owners = HashMap::<ItemId, &String>::new();
+    for item in &items {
+        let description = &descriptions[*item];
+
+        match description.id().or_else(|| database::parse_id(item)) {
+            Some(id) => {
+                if let Some(owner) = owners.get(&id) {
+                    issues.push(Issue::DuplicateId{
+                        item: item.to_string(),
+                        id,
+                        duplicate_of: owner.to_string()
+                    });
+                } else {
+                    owners.insert(id, item);
+                }
+            },
+            None => issues.push(Issue::MissingId(item.to_string()))
+        }
+
+        let mut missing = HashSet::<&String>::new();
+        for recipe in description.recipes() {
+            for ingredient in recipe.ingredients().keys() {
+                if database.resolve(ingredient).is_none() {
+                    missing.insert(ingredient);
+                }
+            }
+        }
+
+        let mut missing: Vec<&String> = missing.into_iter().collect();
+        missing.sort();
+        for ingredient in missing {
+            issues.push(Issue::MissingDescription{
+                item: item.to_string(),
+                ingredient: ingredient.clone()
+            });
+        }
+
+        if description.post_id().is_none()
+            && description.vendor().is_none()
+            && description.recipes().is_empty() {
+            issues.push(Issue::Unobtainable(item.to_string()));
+        }
+
+        let recipes = description.recipes();
+        for (index, recipe) in recipes.iter().enumerate() {
+            if let Some(original) = recipes[..index].iter()
+                .position(|other| other == recipe) {
+                issues.push(Issue::DuplicateRecipe{
+                    item: item.to_string(),
+                    recipe: index,
+                    duplicate_of: original
+                });
+            }
+        }
+    }
+
+    let mut ids: Vec<(String, ItemId)> = database.materials().keys()
+        .map(|id| (database.name(*id), *id))
+        .collect();
+    ids.sort();
+
+    let mut path = Vec::<ItemId>::new();
+    let mut finished = HashSet::<ItemId>::new();
+    let mut cycles = Vec::<Vec<String>>::new();
+    for (_, id) in ids {
+        find_cycles(id, &database, &mut path, &mut finished, &mut cycles);
+    }
+
+    issues.extend(cycles.into_iter().map(Issue::Cycle));
+
+    return issues;
+}
@@ -0,0 +1,124 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::MockApi;
+
+use gw2_tp::cache::ListingCache;
+use gw2_tp::database::{Database, ItemId};
+use gw2_tp::descriptions::MaterialDescription;
+use gw2_tp::market::{MarketSource, Snapshot};
+
+use serde_json::json;
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const IRON_ORE: ItemId = 19699;
+
+fn database() -> Database {
+    let mut descriptions = HashMap::<String, MaterialDescription>::new();
+    descriptions.insert(
+        "iron ore".to_string(),
+        MaterialDescription::new(None, Some(IRON_ORE), None, Vec::new())
+    );
+    return Database::new(&descriptions);
+}
+
+// A cache of iron ore at 1 copper, fetched [age] seconds ago. The fixtures
+// sell it for 40, so the price tells where the listings came from.
+fn cache_fetched_ago(name: &str, age: u64) -> PathBuf {
+    let fetched = SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+        .as_secs() - age;
+    let path = common::fixture_directory(name).join("cache.json");
+    std::fs::write(&path, json!({
+        IRON_ORE.to_string(): {
+            "fetched": fetched,
+            "listings": {
+                "id": IRON_ORE,
+                "buys": [],
+                "sells": [{"listings": 1, "unit_price": 1, "quantity": 250}]
+            }
+        }
+    }).to_string()).unwrap();
+    return path;
+}
+
+fn iron_ore() -> HashSet<ItemId> {
+    return vec![IRON_ORE].into_iter().collect();
+}
+
+#[test]
+fn fresh_listings_come_from_the_cache() {
+    let mut cache = ListingCache::load(&cache_fetched_ago("fresh", 60))
+        .unwrap();
+    assert!(cache.stale(&iron_ore(), Duration::from_secs(300)).is_empty());
+
+    let api = MockApi::start(&common::fixtures());
+    let listings = cache.get_listings(
+        api.url(), &database(), &iron_ore(), Duration::from_secs(300)
+    );
+
+    assert!(listings.errors().is_empty(), "{:?}", listings.errors());
+    assert_eq!(listings.listings()[&IRON_ORE].sells()[0].unit_price(), 1);
+}
+
+#[test]
+fn stale_listings_are_fetched_again() {
+    let path = cache_fetched_ago("stale", 600);
+    let mut cache = ListingCache::load(&path).unwrap();
+    assert_eq!(cache.stale(&iron_ore(), Duration::from_secs(300)), iron_ore());
+
+    // Items that were never fetched are stale too
+    let unknown: HashSet<ItemId> = vec![19750].into_iter().collect();
+    assert_eq!(cache.stale(&unknown, Duration::from_secs(300)), unknown);
+
+    let api = MockApi::start(&common::fixtures());
+    let listings = cache.get_listings(
+        api.url(), &database(), &iron_ore(), Duration::from_secs(300)
+    );
+
+    assert!(listings.errors().is_empty(), "{:?}", listings.errors());
+    assert_eq!(listings.listings()[&IRON_ORE].sells()[0].unit_price(), 40);
+
+    // The fetched listings are fresh once they are saved
+    cache.save().unwrap();
+    let cache = ListingCache::load(&path).unwrap();
+    assert!(cache.stale(&iron_ore(), Duration::from_secs(300)).is_empty());
+    assert_eq!(
+        cache.listings(&iron_ore())[&IRON_ORE].sells()[0].unit_price(), 40
+    );
+}
+
+#[test]
+fn failed_fetches_fall_back_on_the_cache() {
+    let mut cache = ListingCache::load(&cache_fetched_ago("fallback", 600))
+        .unwrap();
+
+    let api = MockApi::start_with(
+        &common::fixtures(), &["--fail", "100", "--fail-status", "400"]
+    );
+    let listings = cache.get_listings(
+        api.url(), &database(), &iron_ore(), Duration::from_secs(300)
+    );
+
+    assert!(!listings.errors().is_empty());
+    assert_eq!(listings.listings()[&IRON_ORE].sells()[0].unit_price(), 1);
+}
+
+#[test]
+fn offline_reads_use_the_cache_however_old() {
+    let age = 30 * 24 * 60 * 60;
+    let cache = ListingCache::load(&cache_fetched_ago("offline", age))
+        .unwrap();
+    let mut snapshot = Snapshot::new(cache);
+
+    let listings = snapshot.listings(&database(), &iron_ore());
+    assert!(listings.errors().is_empty());
+    assert_eq!(listings.listings()[&IRON_ORE].sells()[0].unit_price(), 1);
+
+    let recorded = snapshot.recorded(&iron_ore()).unwrap();
+    let recorded_age = recorded.elapsed().unwrap().as_secs();
+    assert!(age <= recorded_age && recorded_age < age + 60);
+}
@@ -0,0 +1,70 @@
+#![allow(clippy::needless_return)]
+// Each test crate uses only some of these helpers
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// The mock API serving a directory of fixtures on a free local port. It is
+/// stopped when dropped.
+pub struct MockApi {
+    child: Child,
+    url: String
+}
+
+impl MockApi {
+    pub fn start(fixtures: &Path) -> MockApi {
+        return MockApi::start_with(fixtures, &[]);
+    }
+
+    /// Start the mock with more options, such as failures to inject.
+    pub fn start_with(fixtures: &Path, options: &[&str]) -> MockApi {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap()
+            .local_addr().unwrap()
+            .port();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mock_api"))
+            .arg("--port").arg(port.to_string())
+            .arg("--fixtures").arg(fixtures)
+            .args(options)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // The mock only says where it is serving once it is listening
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert!(line.starts_with("Serving"), "mock_api failed to start");
+
+        return MockApi{child, url: format!("http://127.0.0.1:{}", port)};
+    }
+
+    pub fn url(&self) -> &str {
+        return &self.url;
+    }
+}
+
+impl Drop for MockApi {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The fixtures that ship with the repository.
+pub fn fixtures() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+}
+
+/// A fresh, empty directory for fixtures made up by a test.
+pub fn fixture_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir()
+        .join(format!("gw2_tp-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    return directory;
+}
@@ -0,0 +1,116 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::MockApi;
+
+use gw2_tp::descriptions::{self, Recipe};
+use gw2_tp::importer;
+use gw2_tp::tp_rest;
+
+use serde_json::json;
+
+use std::collections::HashMap;
+
+fn recipe(output: u32, ingredients: &[(&str, u32)]) -> Recipe {
+    return Recipe::new(
+        output,
+        ingredients.iter()
+            .map(|(name, quantity)| (name.to_string(), *quantity))
+            .collect()
+    );
+}
+
+#[test]
+fn imports_every_ingredient_of_the_targets() {
+    let api = MockApi::start(&common::fixtures());
+    let mut client = tp_rest::connect(api.url()).unwrap();
+
+    // Hand written descriptions keep what the API does not know about
+    let mut descriptions = descriptions::parse(
+        r#"
+"lump of coal":
+  post_id: 19750
+  vendor: 16
+"#
+    ).unwrap();
+
+    let added = importer::import_with(&mut client, &[46738], &mut descriptions)
+        .unwrap();
+
+    assert_eq!(added, vec![
+        "darksteel ingot",
+        "deldrimor steel ingot",
+        "glob of ectoplasm",
+        "iron ingot",
+        "iron ore",
+        "lump of mithrillium",
+        "lump of primordium",
+        "mithril ingot",
+        "mithril ore",
+        "platinum ore",
+        "steel ingot",
+        "thermocatalytic reagent"
+    ]);
+
+    let ingot = &descriptions["deldrimor steel ingot"];
+    assert_eq!(ingot.id(), Some(46738));
+    assert_eq!(*ingot.post_id(), Some(46738));
+    assert_eq!(ingot.recipes(), &vec![recipe(1, &[
+        ("iron ingot", 20),
+        ("steel ingot", 10),
+        ("darksteel ingot", 20),
+        ("lump of mithrillium", 1)
+    ])]);
+
+    // Items that cannot be traded are identified by their ID instead
+    let mithrillium = &descriptions["lump of mithrillium"];
+    assert_eq!(mithrillium.id(), Some(46742));
+    assert_eq!(*mithrillium.post_id(), None);
+
+    let coal = &descriptions["lump of coal"];
+    assert_eq!(*coal.post_id(), Some(19750));
+    assert_eq!(*coal.vendor(), Some(16));
+    assert!(coal.recipes().is_empty());
+}
+
+#[test]
+fn items_with_the_same_name_are_kept_apart() {
+    // Two different items are both called Gift, and a bundle needs both
+    let directory = common::fixture_directory("names");
+    let items = json!([
+        {"id": 1, "name": "Gift", "flags": []},
+        {"id": 2, "name": "Gift", "flags": ["AccountBound", "NoSell"]},
+        {"id": 3, "name": "Bundle", "flags": []}
+    ]);
+    let recipes = json!([{
+        "id": 10,
+        "output_item_id": 3,
+        "output_item_count": 1,
+        "ingredients": [
+            {"type": "Item", "id": 1, "count": 2},
+            {"type": "Item", "id": 2, "count": 1}
+        ]
+    }]);
+    std::fs::write(directory.join("items.json"), items.to_string()).unwrap();
+    std::fs::write(directory.join("recipes.json"), recipes.to_string())
+        .unwrap();
+    for file_name in &["listings.json", "prices.json"] {
+        std::fs::write(directory.join(file_name), "[]").unwrap();
+    }
+
+    let api = MockApi::start(&directory);
+    let mut client = tp_rest::connect(api.url()).unwrap();
+    let mut descriptions = HashMap::new();
+    let added = importer::import_with(&mut client, &[3], &mut descriptions)
+        .unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(added, vec!["1", "2", "bundle"]);
+    assert_eq!(*descriptions["1"].post_id(), Some(1));
+    assert_eq!(descriptions["2"].id(), Some(2));
+    assert_eq!(
+        descriptions["bundle"].recipes(),
+        &vec![recipe(1, &[("1", 2), ("2", 1)])]
+    );
+}
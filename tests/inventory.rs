@@ -0,0 +1,153 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::MockApi;
+
+use gw2_tp::database::{Database, ItemId};
+use gw2_tp::descriptions::MaterialDescription;
+use gw2_tp::inventory::{self, Location, Valuation};
+use gw2_tp::planning::{self, Options};
+use gw2_tp::targets::Target;
+use gw2_tp::tp_rest::{self, Item};
+
+use std::collections::{HashMap, HashSet};
+
+const IRON_ORE: ItemId = 19699;
+
+fn database() -> Database {
+    let mut descriptions = HashMap::<String, MaterialDescription>::new();
+    descriptions.insert(
+        "iron ore".to_string(),
+        MaterialDescription::new(None, Some(IRON_ORE), None, Vec::new())
+    );
+    return Database::new(&descriptions);
+}
+
+fn listings(api: &MockApi, database: &Database) -> HashMap<ItemId, Item> {
+    let items: HashSet<ItemId> = vec![IRON_ORE].into_iter().collect();
+    let listings = tp_rest::get_listings(api.url(), database, &items);
+    assert!(listings.errors().is_empty(), "{:?}", listings.errors());
+    return listings.into_listings();
+}
+
+#[test]
+fn inventory_is_fetched_from_every_location() {
+    let api = MockApi::start(&common::fixtures());
+    let inventory = inventory::fetch(api.url(), "key").unwrap();
+
+    // Iron ore is in the material storage, the bank and a character's bags
+    assert_eq!(inventory.quantity(IRON_ORE), 50 + 10 + 20);
+    assert_eq!(inventory.quantity(19700), 30 + 5);
+    assert_eq!(inventory.quantity(19702), 25 + 5);
+    assert_eq!(inventory.quantity(19721), 1);
+    assert_eq!(inventory.quantity(19750), 0);
+    assert_eq!(inventory.quantity(19697), 0);
+
+    // Items are taken from the material storage first, then the bank, the
+    // shared inventory and the characters
+    let withdrawals: Vec<(Location, u32)> = inventory.withdraw(IRON_ORE, 70)
+        .iter()
+        .map(|w| (w.location().clone(), w.quantity()))
+        .collect();
+    assert_eq!(withdrawals, vec![
+        (Location::MaterialStorage, 50),
+        (Location::Bank, 10),
+        (Location::Character("Aria Stormvale".to_string()), 10)
+    ]);
+
+    let withdrawals: Vec<(Location, u32)> = inventory.withdraw(19721, 1)
+        .iter()
+        .map(|w| (w.location().clone(), w.quantity()))
+        .collect();
+    assert_eq!(withdrawals, vec![(Location::SharedInventory, 1)]);
+
+    // Names with accents and punctuation are percent-encoded in the path
+    let withdrawals: Vec<(Location, u32)> = inventory.withdraw(19700, 35)
+        .iter()
+        .map(|w| (w.location().clone(), w.quantity()))
+        .collect();
+    assert_eq!(withdrawals, vec![
+        (Location::MaterialStorage, 30),
+        (Location::Character("Zoë D'Arcy".to_string()), 5)
+    ]);
+}
+
+#[test]
+fn fetch_fails_without_an_account() {
+    // Without an account.json, the account endpoints are not found
+    let directory = common::fixture_directory("no-account");
+    for file_name in &[
+        "listings.json", "prices.json", "items.json", "recipes.json"
+    ] {
+        std::fs::copy(
+            common::fixtures().join(file_name), directory.join(file_name)
+        ).unwrap();
+    }
+
+    let api = MockApi::start(&directory);
+    let result = inventory::fetch(api.url(), "key");
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    match result {
+        Err(gw2_tp::tp_rest::Error::Status{status, ..}) => {
+            assert_eq!(status, 404);
+        },
+        result => panic!("expected the fetch to fail: {:?}", result)
+    }
+}
+
+#[test]
+fn owned_items_are_valued_at_their_listings() {
+    let api = MockApi::start(&common::fixtures());
+    let listings = listings(&api, &database());
+    let iron_ore = listings.get(&IRON_ORE);
+
+    // The best buy order is 34, which nets 34 - 2 - 3 after fees, and the
+    // lowest sell listing is 40.
+    assert_eq!(Valuation::Free.unit_value(iron_ore), 0);
+    assert_eq!(Valuation::SellValue.unit_value(iron_ore), 29);
+    assert_eq!(Valuation::InstantBuy.unit_value(iron_ore), 40);
+
+    // Items that cannot be traded are free
+    for valuation in &[
+        Valuation::Free, Valuation::SellValue, Valuation::InstantBuy
+    ] {
+        assert_eq!(valuation.unit_value(None), 0);
+    }
+}
+
+#[test]
+fn plans_charge_owned_items_by_their_valuation() {
+    let api = MockApi::start(&common::fixtures());
+    let inventory = inventory::fetch(api.url(), "key").unwrap();
+    let database = database();
+    let listings = listings(&api, &database);
+
+    // 80 iron ore are owned, so the last 20 have to be bought at 40
+    let targets = vec![
+        Target::new(IRON_ORE, database.name(IRON_ORE), 100, None)
+    ];
+    let plan = |valuation| planning::plan(
+        &targets,
+        &database,
+        &listings,
+        &inventory,
+        &Options::default().with_valuation(valuation)
+    ).unwrap();
+
+    let free = plan(Valuation::Free);
+    assert_eq!(free.owned()[0].quantity(), 80);
+    assert_eq!(free.opportunity_cost(), 0);
+    assert_eq!(free.total_cost(), 20 * 40);
+
+    let sell_value = plan(Valuation::SellValue);
+    assert_eq!(sell_value.owned()[0].quantity(), 80);
+    assert_eq!(sell_value.opportunity_cost(), 80 * 29);
+    assert_eq!(sell_value.total_cost(), 80 * 29 + 20 * 40);
+
+    // Owned items cost as much as buying them, so the plan costs the same
+    // however many of them it uses
+    let instant_buy = plan(Valuation::InstantBuy);
+    assert_eq!(instant_buy.total_cost(), 100 * 40);
+}
@@ -0,0 +1,94 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use gw2_tp::database::Database;
+use gw2_tp::descriptions;
+use gw2_tp::error::{LoadError, Problem};
+use gw2_tp::targets;
+
+fn problems(error: LoadError) -> Vec<(String, Option<usize>, String)> {
+    return match error {
+        LoadError::Invalid{problems, ..} => problems.iter()
+            .map(|p: &Problem| (p.path().clone(), p.line(), p.message().clone()))
+            .collect(),
+        LoadError::Io{error, ..} => panic!("failed to read the file: {}", error)
+    };
+}
+
+#[test]
+fn every_bad_target_is_reported() {
+    let descriptions = descriptions::parse(
+        r#"
+"iron ingot":
+  post_id: 19683
+  vendor: 10
+"#
+    ).unwrap();
+    let database = Database::new(&descriptions);
+
+    let directory = common::fixture_directory("bad-targets");
+    let path = directory.join("targets.yaml");
+    std::fs::write(&path, "\
+- name: iron ingot
+  quantiy: 2
+- name: iron ingot
+  quantity: 0
+# Entries after a bad one are still read
+- iron ingot
+- {name: iron ingot, must: crafted}
+- nothing
+").unwrap();
+
+    let error = targets::load_file(&path, &database).unwrap_err();
+    assert_eq!(problems(error), vec![
+        (
+            "[0].quantiy".to_string(),
+            Some(2),
+            "unknown field `quantiy`, expected one of `item`, `name`, \
+             `quantity`, `must`".to_string()
+        ),
+        (
+            "[1].quantity".to_string(),
+            Some(4),
+            "a target must want at least one item".to_string()
+        ),
+        (
+            "[3].must".to_string(),
+            Some(7),
+            "unknown variant `crafted`, expected `craft` or `buy`".to_string()
+        ),
+        (
+            "[4]".to_string(),
+            None,
+            "no described item matches nothing".to_string()
+        )
+    ]);
+}
+
+#[test]
+fn ingredients_must_be_used() {
+    let error = descriptions::parse(
+        r#"
+"iron ingot":
+  recipes:
+    - "iron ore": 0
+"steel ingot":
+  recipes:
+    - ingredients:
+        "iron ingot": 0
+      output: 2
+"#
+    ).unwrap_err();
+
+    let problems: Vec<(&str, Option<usize>)> = error.iter()
+        .map(|p| (p.path().as_str(), p.line()))
+        .collect();
+    assert_eq!(problems, vec![
+        ("iron ingot.recipes[0].iron ore", Some(4)),
+        ("steel ingot.recipes[0].ingredients.iron ingot", Some(8))
+    ]);
+    assert!(error.iter().all(
+        |p| p.message() == "a recipe must use at least one of an ingredient"
+    ));
+}
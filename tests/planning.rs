@@ -0,0 +1,311 @@
+#![allow(clippy::needless_return)]
+
+use gw2_tp::database::{Database, ItemId};
+use gw2_tp::descriptions;
+use gw2_tp::inventory::Inventory;
+use gw2_tp::planning::{self, Options, Plan, Step};
+use gw2_tp::targets::{Must, Target};
+use gw2_tp::tp_rest::{Item, Price};
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+fn database(source: &str) -> Database {
+    return Database::new(&descriptions::parse(source).unwrap());
+}
+
+// Sell listings with plenty of items at one price for each item.
+fn listings(prices: &[(ItemId, u32)]) -> HashMap<ItemId, Item> {
+    return prices.iter()
+        .map(|(id, price)| (
+            *id,
+            Item::new(*id, Vec::new(), vec![Price::new(1, *price, 1000)])
+        ))
+        .collect();
+}
+
+fn target(
+    database: &Database,
+    name: &str,
+    quantity: u32,
+    must: Option<Must>
+) -> Target {
+    let item = database.resolve(name).unwrap();
+    return Target::new(item, database.name(item), quantity, must);
+}
+
+fn plan(
+    targets: &[Target],
+    database: &Database,
+    listings: &HashMap<ItemId, Item>
+) -> Plan {
+    return planning::plan(
+        targets, database, listings, &Inventory::default(), &Options::default()
+    ).unwrap();
+}
+
+#[test]
+fn surplus_from_another_craft_is_free() {
+    // Crafting b makes 9 x left over, which is exactly what crafting a needs,
+    // so buying a or crafting b from y both cost more.
+    let database = database(
+        r#"
+"r":
+  post_id: 1
+"x":
+  id: 2
+  recipes:
+    - ingredients:
+        "r": 1
+      output: 10
+"y":
+  post_id: 3
+"a":
+  post_id: 4
+  recipes:
+    - "x": 9
+"b":
+  id: 5
+  recipes:
+    - "x": 1
+    - "y": 1
+"#
+    );
+    let listings = listings(&[(1, 100), (3, 50), (4, 95)]);
+    let targets = vec![
+        target(&database, "a", 1, None),
+        target(&database, "b", 1, Some(Must::Craft))
+    ];
+
+    assert_eq!(plan(&targets, &database, &listings).total_cost(), 100);
+}
+
+#[test]
+fn recipe_cycle_is_infeasible() {
+    let database = database(
+        r#"
+"z":
+  id: 1
+  recipes:
+    - "q": 1
+"q":
+  id: 2
+  recipes:
+    - "z": 1
+"#
+    );
+    let targets = vec![target(&database, "z", 1, None)];
+
+    let infeasible = planning::plan(
+        &targets,
+        &database,
+        &HashMap::new(),
+        &Inventory::default(),
+        &Options::default()
+    ).unwrap_err();
+    assert!(infeasible.cycle());
+    assert_eq!(infeasible.item(), database.resolve("z").unwrap());
+}
+
+#[test]
+fn each_target_pays_for_its_own_crafts() {
+    // The crafting queue merges the crafts into one of 20 ingots, but each
+    // target still pays for the ten that it needs.
+    let database = database(
+        r#"
+"ore":
+  post_id: 1
+"ingot":
+  id: 2
+  recipes:
+    - "ore": 2
+"#
+    );
+    let listings = listings(&[(1, 60)]);
+    let targets = vec![
+        target(&database, "ingot", 10, Some(Must::Craft)),
+        target(&database, "ingot", 10, Some(Must::Craft))
+    ];
+
+    let plan = plan(&targets, &database, &listings);
+    assert_eq!(plan.total_cost(), 2400);
+    for target_plan in plan.targets() {
+        assert_eq!(target_plan.cost(), 1200);
+        match target_plan.steps().as_slice() {
+            [Step::Craft(step)] => assert_eq!(step.craft().crafts(), 10),
+            steps => panic!("Expected a single craft, got {:?}", steps)
+        }
+    }
+}
+
+#[test]
+fn crafts_come_after_the_crafts_whose_surplus_they_use() {
+    let database = database(
+        r#"
+"r":
+  post_id: 1
+"x":
+  id: 2
+  recipes:
+    - ingredients:
+        "r": 1
+      output: 10
+"y":
+  post_id: 3
+"a":
+  post_id: 4
+  recipes:
+    - "x": 9
+"b":
+  id: 5
+  recipes:
+    - "x": 1
+    - "y": 1
+"#
+    );
+    let listings = listings(&[(1, 100), (3, 50), (4, 95)]);
+    let targets = vec![
+        target(&database, "a", 1, None),
+        target(&database, "b", 1, Some(Must::Craft))
+    ];
+
+    let plan = plan(&targets, &database, &listings);
+    let queue: Vec<ItemId> =
+        plan.crafts().iter().map(|craft| craft.item()).collect();
+    let position = |name: &str| {
+        let item = database.resolve(name).unwrap();
+        queue.iter().position(|queued| *queued == item).unwrap()
+    };
+    assert_eq!(queue.len(), 3);
+    assert!(position("x") < position("a"));
+    assert!(position("x") < position("b"));
+}
+
+#[test]
+fn greedy_completion_stays_within_the_budget() {
+    // Every level of the chain can be crafted from the next level alone, or
+    // along with a cheap item of its own. The chain ends in an item with too
+    // few listings, so every one of the ways to complete the plan runs into a
+    // dead end.
+    let mut source = String::from("\"i18\":\n  post_id: 18\n");
+    let mut prices = vec![(18, 100)];
+    for level in 0..18 {
+        source += &format!(
+            concat!(
+                "\"x{0}\":\n  post_id: {2}\n",
+                "\"i{0}\":\n  id: {0}\n  recipes:\n",
+                "    - \"i{1}\": 1\n",
+                "    - \"i{1}\": 1\n      \"x{0}\": 1\n"
+            ),
+            level, level + 1, 100 + level
+        );
+        prices.push((100 + level, 1));
+    }
+    let database = database(&source);
+    let mut listings = listings(&prices);
+    listings.insert(18, Item::new(18, Vec::new(), vec![Price::new(1, 100, 1)]));
+    let targets = vec![target(&database, "i0", 2, None)];
+
+    let started = Instant::now();
+    let infeasible = planning::plan(
+        &targets,
+        &database,
+        &listings,
+        &Inventory::default(),
+        &Options::default().with_node_limit(100)
+    ).unwrap_err();
+    assert_eq!(infeasible.item(), database.resolve("i18").unwrap());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn buying_takes_the_cheapest_listings_first() {
+    let database = database(
+        r#"
+"ore":
+  post_id: 1
+"#
+    );
+    let listings = HashMap::from([(1, Item::new(1, Vec::new(), vec![
+        Price::new(1, 5, 10),
+        Price::new(2, 7, 100)
+    ]))]);
+    let targets = vec![target(&database, "ore", 15, None)];
+
+    assert_eq!(plan(&targets, &database, &listings).total_cost(), 85);
+}
+
+#[test]
+fn crafting_is_chosen_when_it_is_cheaper() {
+    let database = database(
+        r#"
+"ore":
+  post_id: 1
+"ingot":
+  post_id: 2
+  recipes:
+    - ingredients:
+        "ore": 3
+      output: 2
+"#
+    );
+    let listings = listings(&[(1, 10), (2, 25)]);
+    let targets = vec![target(&database, "ingot", 3, None)];
+
+    // Two crafts make four ingots for 60, where buying three costs 75
+    let plan = plan(&targets, &database, &listings);
+    assert_eq!(plan.total_cost(), 60);
+    assert_eq!(plan.crafts().len(), 1);
+    assert_eq!(plan.crafts()[0].crafts(), 2);
+    assert_eq!(plan.leftovers().len(), 1);
+    assert_eq!(plan.leftovers()[0].quantity(), 1);
+}
+
+#[test]
+fn uses_of_an_item_can_be_acquired_in_different_ways() {
+    // The cheapest plan crafts one m1, buys the only listed one and gets the
+    // last one from a vendor.
+    let database = database(
+        r#"
+"m1":
+  post_id: 1
+  vendor: 37
+  recipes:
+    - "m4": 3
+"m4":
+  post_id: 4
+  vendor: 24
+"#
+    );
+    let listings = HashMap::from([
+        (1, Item::new(1, Vec::new(), vec![Price::new(1, 7, 1)])),
+        (4, Item::new(4, Vec::new(), vec![Price::new(1, 5, 2)]))
+    ]);
+    let targets = vec![
+        target(&database, "m1", 1, None),
+        target(&database, "m1", 2, None)
+    ];
+
+    assert_eq!(plan(&targets, &database, &listings).total_cost(), 78);
+}
+
+#[test]
+fn undescribed_target_is_infeasible() {
+    let database = database(
+        r#"
+"ore":
+  post_id: 1
+"#
+    );
+    let targets = vec![Target::new(99, "mystery".to_string(), 1, None)];
+
+    let infeasible = planning::plan(
+        &targets,
+        &database,
+        &HashMap::new(),
+        &Inventory::default(),
+        &Options::default()
+    ).unwrap_err();
+    assert_eq!(infeasible.item(), 99);
+    assert!(!infeasible.described());
+}
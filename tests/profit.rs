@@ -0,0 +1,78 @@
+#![allow(clippy::needless_return)]
+
+use gw2_tp::profit::{self, Sale};
+use gw2_tp::tp_rest::{Item, Price};
+
+fn buy_orders(tiers: &[(u32, u32)]) -> Item {
+    return Item::new(
+        1,
+        tiers.iter()
+            .map(|(unit_price, quantity)| Price::new(1, *unit_price, *quantity))
+            .collect(),
+        Vec::new()
+    );
+}
+
+fn sell(unit_price: u32, quantity: u32) -> Sale {
+    return profit::sell_to_buy_orders(
+        &buy_orders(&[(unit_price, quantity)]),
+        quantity
+    ).unwrap();
+}
+
+#[test]
+fn listing_and_exchange_fees_are_charged_on_every_item() {
+    // 5% of 100 to list and 10% to sell, for each of the 3 items
+    let sale = sell(100, 3);
+    assert_eq!(sale.revenue(), 300);
+    assert_eq!(sale.fees(), 3 * (5 + 10));
+    assert_eq!(sale.net(), 255);
+
+    // At 1000 the fees are 50 and 100 apiece
+    assert_eq!(sell(1000, 1).fees(), 150);
+}
+
+#[test]
+fn fees_are_rounded_to_the_nearest_copper() {
+    // 5% of 30 is 1.5 and 10% is 3
+    assert_eq!(sell(30, 1).fees(), 2 + 3);
+
+    // 5% of 29 is 1.45 and 10% is 2.9
+    assert_eq!(sell(29, 1).fees(), 1 + 3);
+
+    // 5% of 45 is 2.25 and 10% is 4.5
+    assert_eq!(sell(45, 1).fees(), 2 + 5);
+}
+
+#[test]
+fn fees_are_at_least_one_copper() {
+    // 5% of 4 is 0.2 and 10% is 0.4, which would both round to nothing
+    assert_eq!(sell(4, 1).fees(), 1 + 1);
+    assert_eq!(sell(4, 5).fees(), 5 * (1 + 1));
+
+    // Fees never take more than the items sell for
+    assert_eq!(sell(1, 2).fees(), 2);
+    assert_eq!(sell(1, 2).net(), 0);
+}
+
+#[test]
+fn sales_fill_the_highest_buy_orders_first() {
+    let item = buy_orders(&[(100, 2), (90, 3), (80, 10)]);
+
+    let sale = profit::sell_to_buy_orders(&item, 4).unwrap();
+    assert_eq!(sale.revenue(), 2 * 100 + 2 * 90);
+    assert_eq!(sale.fees(), 2 * (5 + 10) + 2 * (5 + 9));
+    assert_eq!(sale.unit_price(), 90);
+
+    let sale = profit::sell_to_buy_orders(&item, 15).unwrap();
+    assert_eq!(sale.revenue(), 2 * 100 + 3 * 90 + 10 * 80);
+    assert_eq!(sale.fees(), 2 * (5 + 10) + 3 * (5 + 9) + 10 * (4 + 8));
+    assert_eq!(sale.unit_price(), 80);
+}
+
+#[test]
+fn sales_beyond_the_buy_orders_fail() {
+    let item = buy_orders(&[(100, 2), (90, 3)]);
+    assert_eq!(profit::sell_to_buy_orders(&item, 6), None);
+    assert_eq!(profit::resale_value(&item, 6), 2 * 85 + 3 * 76);
+}
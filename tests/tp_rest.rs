@@ -0,0 +1,185 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::MockApi;
+
+use gw2_tp::database::{Database, ItemId};
+use gw2_tp::descriptions::MaterialDescription;
+use gw2_tp::tp_rest::{self, RateLimiter, MAX_ATTEMPTS, MAX_IDS_PER_REQUEST};
+
+use serde_json::json;
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+// A database of tradable items with the given post_ids.
+fn database(items: &[(&str, u32)]) -> Database {
+    let descriptions: HashMap<String, MaterialDescription> = items.iter()
+        .map(|(name, post_id)| (
+            name.to_string(),
+            MaterialDescription::new(None, Some(*post_id), None, Vec::new())
+        ))
+        .collect();
+    return Database::new(&descriptions);
+}
+
+fn resolve(database: &Database, names: &[&str]) -> HashSet<ItemId> {
+    return names.iter()
+        .map(|name| database.resolve(name).unwrap())
+        .collect();
+}
+
+#[test]
+fn listings_are_fetched_in_chunks() {
+    // More items than fit into two requests, each with its own price
+    let count = 2 * MAX_IDS_PER_REQUEST as u32 + 50;
+    let directory = common::fixture_directory("chunks");
+    let listings: Vec<_> = (1..=count)
+        .map(|id| json!({
+            "id": id,
+            "buys": [],
+            "sells": [{"listings": 1, "unit_price": id, "quantity": 10}]
+        }))
+        .collect();
+    std::fs::write(
+        directory.join("listings.json"), json!(listings).to_string()
+    ).unwrap();
+    for file_name in &["prices.json", "items.json", "recipes.json"] {
+        std::fs::write(directory.join(file_name), "[]").unwrap();
+    }
+
+    let names: Vec<String> =
+        (1..=count).map(|id| format!("item {}", id)).collect();
+    let database = database(
+        &names.iter()
+            .zip(1..=count)
+            .map(|(name, id)| (name.as_str(), id))
+            .collect::<Vec<_>>()
+    );
+    let items: HashSet<ItemId> = names.iter()
+        .map(|name| database.resolve(name).unwrap())
+        .collect();
+
+    let api = MockApi::start(&directory);
+    let result = tp_rest::get_listings(api.url(), &database, &items);
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert!(result.errors().is_empty(), "{:?}", result.errors());
+    assert!(result.failed().is_empty());
+    assert_eq!(result.listings().len(), count as usize);
+    for (id, name) in (1..=count).zip(&names) {
+        let item = &result.listings()[&database.resolve(name).unwrap()];
+        assert_eq!(item.sells()[0].unit_price(), id);
+    }
+}
+
+#[test]
+fn untradable_items_are_left_out() {
+    // The API answers with only the listings it has, and a partial content
+    // status, when some of the items cannot be traded.
+    let database = database(&[
+        ("iron ore", 19699),
+        ("lump of coal", 19750),
+        ("lump of mithrillium", 46742)
+    ]);
+    let items = resolve(
+        &database, &["iron ore", "lump of coal", "lump of mithrillium"]
+    );
+
+    let api = MockApi::start(&common::fixtures());
+    let result = tp_rest::get_listings(api.url(), &database, &items);
+
+    assert!(result.errors().is_empty(), "{:?}", result.errors());
+    assert!(result.failed().is_empty());
+    assert_eq!(
+        result.listings().keys().copied().collect::<HashSet<ItemId>>(),
+        resolve(&database, &["iron ore", "lump of coal"])
+    );
+    let iron_ore = &result.listings()[&database.resolve("iron ore").unwrap()];
+    assert_eq!(iron_ore.sells()[0].unit_price(), 40);
+}
+
+#[test]
+fn no_listings_when_nothing_can_be_traded() {
+    // The API answers with not found when none of the items can be traded,
+    // which is not a failure.
+    let database = database(&[
+        ("lump of mithrillium", 46742),
+        ("spool of thick elonian cord", 46745)
+    ]);
+    let items = resolve(
+        &database, &["lump of mithrillium", "spool of thick elonian cord"]
+    );
+
+    let api = MockApi::start(&common::fixtures());
+    let result = tp_rest::get_listings(api.url(), &database, &items);
+
+    assert!(result.errors().is_empty(), "{:?}", result.errors());
+    assert!(result.failed().is_empty());
+    assert!(result.listings().is_empty());
+}
+
+#[test]
+fn failed_requests_are_retried_with_backoff() {
+    let api = MockApi::start_with(
+        &common::fixtures(), &["--fail", "2", "--fail-status", "429"]
+    );
+    let mut client = tp_rest::connect(api.url()).unwrap();
+
+    let started = Instant::now();
+    let items = tp_rest::get_items(&mut client, &[19699]).unwrap();
+
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name(), "Iron Ore");
+
+    // The second retry waits twice as long as the first
+    assert!(Duration::from_millis(1500) <= started.elapsed());
+}
+
+#[test]
+fn requests_give_up_after_the_last_attempt() {
+    let api = MockApi::start_with(&common::fixtures(), &["--fail", "10"]);
+    let mut client = tp_rest::connect(api.url()).unwrap();
+
+    match tp_rest::get_items(&mut client, &[19699]) {
+        Err(tp_rest::Error::Status{status, text}) => {
+            assert_eq!(status, 503);
+            assert_eq!(text, format!("failure {} of 10", MAX_ATTEMPTS));
+        },
+        result => panic!("expected the request to fail: {:?}", result)
+    }
+}
+
+#[test]
+fn permanent_failures_are_not_retried() {
+    let api = MockApi::start_with(
+        &common::fixtures(), &["--fail", "3", "--fail-status", "400"]
+    );
+    let mut client = tp_rest::connect(api.url()).unwrap();
+
+    match tp_rest::get_items(&mut client, &[19699]) {
+        Err(tp_rest::Error::Status{status, text}) => {
+            assert_eq!(status, 400);
+            assert_eq!(text, "failure 1 of 3");
+        },
+        result => panic!("expected the request to fail: {:?}", result)
+    }
+}
+
+#[test]
+fn rate_limiter_waits_once_the_burst_is_used_up() {
+    // 600 a minute is one every 100 ms, after a burst of 3
+    let limiter = RateLimiter::new(600, 3);
+
+    let started = Instant::now();
+    for _ in 0..3 {
+        limiter.acquire();
+    }
+    assert!(started.elapsed() < Duration::from_millis(50));
+
+    for _ in 0..2 {
+        limiter.acquire();
+    }
+    assert!(Duration::from_millis(190) <= started.elapsed());
+}